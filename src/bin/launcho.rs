@@ -32,17 +32,72 @@ enum Action {
     #[clap(long, action)]
     ipvs: bool,
   },
+  /// Prints a process's output. PROCESS is the name of a process or job from the target, which
+  /// gets its newest version, or (a prefix of) the name of a particular version.
+  #[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
   Logs {
-    process: String,
+    #[clap(subcommand)]
+    search:       Option<LogsAction>,
+    #[clap(required = true)]
+    process:      Option<String>,
+    #[clap(long, action)]
+    hook:         bool,
+    /// Keep printing output as it comes in, moving on to newer versions as they launch.
+    #[clap(short, long, action, conflicts_with_all = ["hook", "previous", "all_versions"])]
+    follow:       bool,
+    /// Get the version before the newest one, like after a crash.
+    #[clap(long, action, conflicts_with = "all_versions")]
+    previous:     bool,
+    /// Combine the output of every version we still have.
+    #[clap(long, action)]
+    all_versions: bool,
+    #[clap(flatten)]
+    options:      LogArgs,
   },
   RestartProcess {
     process: String,
   },
+  /// Lists crash reports for processes that exited unexpectedly.
+  Crashes {
+    process: Option<String>,
+  },
+  #[clap(subcommand, aliases = &["j", "jobs"])]
+  Job(JobAction),
+  Gc {
+    #[clap(long, action)]
+    dry_run: bool,
+  },
   #[clap(subcommand)]
   Uncommon(UncommonAction),
   Version,
 }
 
+#[derive(Debug, clap::Args)]
+struct LogArgs {
+  /// Prefix each line with when it was received.
+  #[clap(long, action)]
+  timestamps:  bool,
+  #[clap(long, action)]
+  stderr_only: bool,
+  /// Only show lines from this long ago onwards, like "10m".
+  #[clap(long, value_parser = launcho::config::parse_duration)]
+  since:       Option<std::time::Duration>,
+  /// Only show this many lines from the end.
+  #[clap(long)]
+  tail:        Option<usize>,
+}
+
+impl LogArgs {
+  fn to_options(&self) -> launcho::LogOptions {
+    launcho::LogOptions {
+      timestamps:  self.timestamps,
+      stderr_only: self.stderr_only,
+      since_secs:  self.since.map(|since| since.as_secs()),
+      tail:        self.tail,
+    }
+  }
+}
+
 #[derive(Debug, clap::Subcommand)]
 enum TargetAction {
   Get,
@@ -75,10 +130,34 @@ enum SecretAction {
   Ls,
 }
 
+#[derive(Debug, clap::Subcommand)]
+enum LogsAction {
+  /// Searches the output of every process on the server for lines matching a regex.
+  Grep {
+    pattern:   String,
+    /// Only search this process (by name, or a version's name), and can be given repeatedly.
+    #[clap(short, long = "process")]
+    processes: Vec<String>,
+    /// Only search lines from this long ago onwards, like "10m".
+    #[clap(long, value_parser = launcho::config::parse_duration)]
+    since:     Option<std::time::Duration>,
+  },
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum JobAction {
+  Run { name: String },
+}
+
 #[derive(Debug, clap::Subcommand)]
 enum UncommonAction {
   ClearLaunchRateLimits,
   Ping,
+  /// Used by the server to run isolated processes.
+  #[clap(hide = true)]
+  Sandbox {
+    config: String,
+  },
 }
 
 fn handle_error_response(response: ClientResponse) -> ClientResponse {
@@ -102,6 +181,30 @@ fn handle_success_or_error(response: ClientResponse) {
   }
 }
 
+fn print_crash_report(report: &launcho::CrashReport) {
+  let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+  let how = match report.signal {
+    Some(signal) => format!("killed by signal {}", signal),
+    None => format!("exit status {}", report.exit_status),
+  };
+  println!(
+    "{} ({}): {}{}, after running {}s, {}s ago",
+    report.name,
+    report.process_name,
+    how,
+    if report.core_dumped {
+      " (core dumped)"
+    } else {
+      ""
+    },
+    report.run_time_secs,
+    now.saturating_sub(report.approx_time),
+  );
+  for line in report.output_tail.lines() {
+    println!("    {}", line);
+  }
+}
+
 fn progress_bar(prefix: &str, bytes: f64, full_size: f64) {
   eprint!(
     "\r{} {:.2}% ({:.2} / {:.2} MiB)",
@@ -112,11 +215,8 @@ fn progress_bar(prefix: &str, bytes: f64, full_size: f64) {
   );
 }
 
-async fn main_result() -> Result<(), Error> {
-  dotenv::dotenv().ok();
-  let args: Args = Args::parse();
-
-  Ok(match args.action {
+async fn main_result(args: Args) -> Result<(), Error> {
+  match args.action {
     Action::Version => {
       println!("launcho {}", env!("CARGO_PKG_VERSION"));
     }
@@ -154,7 +254,7 @@ async fn main_result() -> Result<(), Error> {
             });
             guarantee_launcho_directory()?;
             let default_config = include_str!("../default-config.yaml");
-            std::fs::write(&config_path, &default_config)?;
+            std::fs::write(&config_path, default_config)?;
             default_config.to_string()
           }
           (_, Err(e)) => bail!("Failed to read config file at {:?}: {}", config_path, e),
@@ -191,8 +291,7 @@ async fn main_result() -> Result<(), Error> {
         println!("No changes -- not updating");
       } else {
         handle_success_or_error(
-          launcho::send_request(launcho::ClientRequest::SetTarget { target: new_target })
-            .await?,
+          launcho::send_request(launcho::ClientRequest::SetTarget { target: new_target }).await?,
         );
       }
     }
@@ -231,9 +330,8 @@ async fn main_result() -> Result<(), Error> {
       }
     }
     Action::Secret(SecretAction::Ls) => {
-      let response = handle_error_response(
-        launcho::send_request(launcho::ClientRequest::ListSecrets).await?,
-      );
+      let response =
+        handle_error_response(launcho::send_request(launcho::ClientRequest::ListSecrets).await?);
       match response {
         ClientResponse::SecretList { secrets } =>
           for name in secrets {
@@ -264,23 +362,133 @@ async fn main_result() -> Result<(), Error> {
         _ => panic!("Unexpected response: {:?}", response),
       }
     }
-    Action::Logs { process } => {
+    Action::Logs {
+      search: Some(LogsAction::Grep {
+        pattern,
+        processes,
+        since,
+      }),
+      ..
+    } => {
+      let response = handle_error_response(
+        launcho::send_request(launcho::ClientRequest::SearchLogs {
+          pattern,
+          processes,
+          since_secs: since.map(|since| since.as_secs()),
+        })
+        .await?,
+      );
+      match response {
+        ClientResponse::LogMatches { matches, .. } if matches.is_empty() =>
+          println!("No matching lines"),
+        ClientResponse::LogMatches { matches, truncated } => {
+          if truncated {
+            println!("(Too many matches, so only showing the newest {})", matches.len());
+          }
+          for line in &matches {
+            println!(
+              "{} {}/{}: {}",
+              launcho::server::format_timestamp(line.time_ms),
+              line.process_name,
+              line.name,
+              line.text
+            );
+          }
+        }
+        _ => panic!("Unexpected response: {:?}", response),
+      }
+    }
+    Action::Logs {
+      process: Some(process),
+      follow: true,
+      options,
+      ..
+    } => {
+      let (client, host, port) = make_authenticated_client()?;
+      let response = client
+        .get(format!("https://launcho:{}/logs", port))
+        .query(&[("name", process)])
+        .query(&options.to_options())
+        .send()
+        .await
+        .with_context(|| format!("Following logs from {} failed", host))?;
+      if !response.status().is_success() {
+        bail!("Following logs failed: {}", response.text().await?);
+      }
+      let mut stream = response.bytes_stream();
+      let mut stdout = std::io::stdout();
+      while let Some(chunk) = stream.try_next().await? {
+        stdout.write_all(&chunk)?;
+        stdout.flush()?;
+      }
+    }
+    Action::Logs {
+      process: Some(process),
+      hook,
+      previous,
+      all_versions,
+      options,
+      ..
+    } => {
+      let versions = match (previous, all_versions) {
+        (true, _) => launcho::LogVersions::Previous,
+        (_, true) => launcho::LogVersions::All,
+        _ => launcho::LogVersions::Current,
+      };
       let response = handle_error_response(
-        launcho::send_request(launcho::ClientRequest::GetLogs { name: process }).await?,
+        launcho::send_request(launcho::ClientRequest::GetLogs {
+          name: process,
+          hook,
+          options: options.to_options(),
+          versions,
+        })
+        .await?,
       );
       match response {
         ClientResponse::Logs { name, output } => {
           println!("Process: {}", name);
-          println!("{}", output);
+          print!("{}", output);
         }
         _ => panic!("Unexpected response: {:?}", response),
       }
     }
+    // Clap makes sure we have one or the other.
+    Action::Logs { .. } => unreachable!(),
     Action::RestartProcess { process } => {
       handle_success_or_error(
         launcho::send_request(launcho::ClientRequest::Restart { name: process }).await?,
       );
     }
+    Action::Crashes { process } => {
+      let response = handle_error_response(
+        launcho::send_request(launcho::ClientRequest::GetCrashReports { process }).await?,
+      );
+      match response {
+        ClientResponse::CrashReports { reports } if reports.is_empty() =>
+          println!("No crash reports"),
+        ClientResponse::CrashReports { reports } =>
+          for report in &reports {
+            print_crash_report(report);
+          },
+        _ => panic!("Unexpected response: {:?}", response),
+      }
+    }
+    Action::Job(JobAction::Run { name }) => {
+      handle_success_or_error(
+        launcho::send_request(launcho::ClientRequest::RunJob { name }).await?,
+      );
+    }
+    Action::Gc { dry_run } => {
+      let response = handle_error_response(
+        launcho::send_request(launcho::ClientRequest::GarbageCollect { dry_run }).await?,
+      );
+      match response {
+        ClientResponse::Success {
+          message: Some(message),
+        } => println!("{}", message.trim()),
+        _ => panic!("Unexpected response: {:?}", response),
+      }
+    }
     Action::Resource(ResourceAction::Up { name, file }) => {
       let full_size = std::fs::metadata(&file)?.len();
       let mut bytes_written = 0;
@@ -310,7 +518,6 @@ async fn main_result() -> Result<(), Error> {
         } => println!("{}", message),
         _ => panic!("Unexpected response: {:?}", response),
       }
-      ()
     }
     Action::Resource(ResourceAction::Down { id, file }) => {
       let (client, host, port) = make_authenticated_client()?;
@@ -345,9 +552,8 @@ async fn main_result() -> Result<(), Error> {
       );
     }
     Action::Resource(ResourceAction::Ls) => {
-      let response = handle_error_response(
-        launcho::send_request(launcho::ClientRequest::ListResources).await?,
-      );
+      let response =
+        handle_error_response(launcho::send_request(launcho::ClientRequest::ListResources).await?);
       match response {
         ClientResponse::ResourceList { resources } => {
           println!("Found {} resources", resources.len());
@@ -367,12 +573,33 @@ async fn main_result() -> Result<(), Error> {
       let pong = launcho::send_request(launcho::ClientRequest::Ping).await?;
       println!("{:#?}", pong);
     }
-  })
+    Action::Uncommon(UncommonAction::Sandbox { .. }) => unreachable!(),
+  }
+  Ok(())
 }
 
-#[tokio::main]
-async fn main() {
-  match main_result().await {
+fn main() {
+  dotenv::dotenv().ok();
+  let args: Args = Args::parse();
+
+  // The sandbox helper has to be single threaded to unshare namespaces, so it runs before tokio.
+  if let Action::Uncommon(UncommonAction::Sandbox { config }) = &args.action {
+    #[cfg(not(target_os = "linux"))]
+    let result: Result<(), Error> = {
+      let _ = config;
+      Err(anyhow::anyhow!("sandboxing only works on Linux"))
+    };
+    #[cfg(target_os = "linux")]
+    let result = launcho::sandbox::sandbox_main(config);
+    if let Err(e) = result {
+      eprintln!("launcho sandbox: {:#}", e);
+      std::process::exit(127);
+    }
+    return;
+  }
+
+  let runtime = tokio::runtime::Runtime::new().expect("Failed to start tokio");
+  match runtime.block_on(main_result(args)) {
     Ok(()) => {}
     Err(e) => {
       eprintln!("Error: {}", e);
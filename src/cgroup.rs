@@ -0,0 +1,149 @@
+use std::{
+  os::unix::ffi::OsStrExt,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+
+use crate::config::LimitsSpec;
+
+static CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// Every process gets its own cgroup under this one.
+static SLICE_NAME: &str = "launcho.slice";
+static CONTROLLERS: &[&str] = &["cpu", "memory", "pids", "io"];
+static CPU_PERIOD_MICROS: u64 = 100_000;
+/// The kernel won't take a quota below 1ms per period.
+pub const MIN_MILLICPUS: u64 = 1000 * 1000 / CPU_PERIOD_MICROS;
+
+/// Checks if we can manage cgroups, which requires a cgroup v2 hierarchy that we can write to.
+pub fn is_available() -> bool {
+  let root = Path::new(CGROUP_ROOT);
+  root.join("cgroup.controllers").exists() && unsafe { libc::geteuid() } == 0
+}
+
+fn enable_controllers(path: &Path) {
+  // Controllers that the kernel doesn't have fail individually, and only matter if some limit
+  // needs them, in which case setting that limit will fail.
+  for controller in CONTROLLERS {
+    std::fs::write(path.join("cgroup.subtree_control"), format!("+{}", controller)).ok();
+  }
+}
+
+/// Creates a cgroup for a process, and applies its limits.
+pub fn create(name: &str, limits: &LimitsSpec) -> Result<PathBuf, Error> {
+  let slice = Path::new(CGROUP_ROOT).join(SLICE_NAME);
+  crate::already_exists_ok(std::fs::create_dir(&slice))
+    .with_context(|| format!("Failed to create cgroup {:?}", slice))?;
+  enable_controllers(Path::new(CGROUP_ROOT));
+  enable_controllers(&slice);
+
+  let path = slice.join(name);
+  crate::already_exists_ok(std::fs::create_dir(&path))
+    .with_context(|| format!("Failed to create cgroup {:?}", path))?;
+  let write = |file: &str, value: String| {
+    std::fs::write(path.join(file), &value)
+      .with_context(|| format!("Failed to set {} to {:?} in {:?}", file, value, path))
+  };
+  let result = (|| {
+    if let Some(memory) = limits.memory {
+      write("memory.max", memory.0.to_string())?;
+      // If anything gets OOM killed, kill the whole process, rather than leaving it half-dead.
+      write("memory.oom.group", "1".to_string())?;
+    }
+    if let Some(cpu) = limits.cpu {
+      let quota = cpu.millicpus * CPU_PERIOD_MICROS / 1000;
+      write("cpu.max", format!("{} {}", quota, CPU_PERIOD_MICROS))?;
+    }
+    if let Some(pids) = limits.pids {
+      write("pids.max", pids.to_string())?;
+    }
+    if let Some(io_weight) = limits.io_weight {
+      write("io.weight", format!("default {}", io_weight))?;
+    }
+    Ok(())
+  })();
+  if let Err(e) = result {
+    std::fs::remove_dir(&path).ok();
+    return Err(e);
+  }
+  Ok(path)
+}
+
+/// Makes a command move itself into a cgroup before it execs. This has to happen while still
+/// privileged, so the command must not use `Command::uid` or `Command::gid`, and instead pass
+/// its identity here to be switched to afterwards.
+pub fn enter_on_spawn(
+  command: &mut tokio::process::Command,
+  path: &Path,
+  uid: Option<u32>,
+  gid: Option<u32>,
+) -> Result<(), Error> {
+  let procs = std::ffi::CString::new(path.join("cgroup.procs").as_os_str().as_bytes())?;
+  let pre_exec = move || {
+    // Only async-signal-safe calls are allowed in here, so no allocating.
+    unsafe {
+      let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+      if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+      // Writing zero moves the writing process.
+      let written = libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+      let error = std::io::Error::last_os_error();
+      libc::close(fd);
+      if written != 1 {
+        return Err(error);
+      }
+      if let Some(gid) = gid {
+        if libc::setgid(gid) != 0 {
+          return Err(std::io::Error::last_os_error());
+        }
+      }
+      if let Some(uid) = uid {
+        // Drop our supplementary groups, like Command::uid does.
+        if libc::getuid() == 0 && libc::setgroups(0, std::ptr::null()) != 0 {
+          return Err(std::io::Error::last_os_error());
+        }
+        if libc::setuid(uid) != 0 {
+          return Err(std::io::Error::last_os_error());
+        }
+      }
+    }
+    Ok(())
+  };
+  unsafe { command.pre_exec(pre_exec) };
+  Ok(())
+}
+
+/// Checks if anything in the cgroup has been killed for running out of memory.
+pub fn was_oom_killed(path: &Path) -> bool {
+  let Ok(events) = std::fs::read_to_string(path.join("memory.events")) else {
+    return false;
+  };
+  events
+    .lines()
+    .filter_map(|line| line.strip_prefix("oom_kill "))
+    .any(|count| count.trim().parse::<u64>().is_ok_and(|count| count > 0))
+}
+
+/// Kills everything left in the cgroup.
+pub fn kill_all(path: &Path) -> Result<(), Error> {
+  if std::fs::write(path.join("cgroup.kill"), "1").is_ok() {
+    return Ok(());
+  }
+  // Kernels before 5.14 don't have cgroup.kill, so kill each process ourselves.
+  let procs = std::fs::read_to_string(path.join("cgroup.procs"))
+    .with_context(|| format!("Failed to list processes in {:?}", path))?;
+  for pid in procs.lines().filter_map(|line| line.trim().parse::<i32>().ok()) {
+    unsafe { libc::kill(pid, libc::SIGKILL) };
+  }
+  Ok(())
+}
+
+/// Removes a cgroup, which must have no processes left in it.
+pub fn remove(path: &Path) -> Result<(), Error> {
+  match std::fs::remove_dir(path) {
+    Err(e) if e.kind() != std::io::ErrorKind::NotFound =>
+      Err(e).with_context(|| format!("Failed to remove cgroup {:?}", path)),
+    _ => Ok(()),
+  }
+}
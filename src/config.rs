@@ -1,6 +1,6 @@
 use std::collections::{BTreeMap, HashMap};
 
-use anyhow::{bail, Context, Error};
+use anyhow::{anyhow, bail, Context, Error};
 use serde::{Deserialize, Serialize};
 
 use crate::get_extra_secrets_path;
@@ -11,6 +11,89 @@ pub struct ServerSpec {
   pub admin_host:     String,
   pub admin_port:     u16,
   pub loopback_ports: (u16, u16),
+  #[serde(default)]
+  pub retention:      RetentionSpec,
+  #[serde(default)]
+  pub logs:           LogsSpec,
+  #[serde(default)]
+  pub orphans:        OrphanPolicy,
+}
+
+/// What to do on start-up with processes left running by a previous launcho server.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanPolicy {
+  /// Keep running processes that match the current target, and terminate the rest.
+  Adopt,
+  /// Terminate all of them, and launch fresh copies.
+  #[default]
+  Terminate,
+}
+
+/// How long to hang on to processes that have exited, for `launcho status` and `launcho logs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionSpec {
+  /// The number of exited versions to keep for each process.
+  #[serde(default = "default_keep_exited")]
+  pub keep_exited: usize,
+  /// Exited versions older than this are dropped, even if within keep_exited.
+  #[serde(default = "default_max_age")]
+  pub max_age:     HumanDuration,
+}
+
+fn default_keep_exited() -> usize {
+  5
+}
+
+fn default_max_age() -> HumanDuration {
+  HumanDuration::from_secs(24 * 60 * 60)
+}
+
+impl Default for RetentionSpec {
+  fn default() -> Self {
+    Self {
+      keep_exited: default_keep_exited(),
+      max_age:     default_max_age(),
+    }
+  }
+}
+
+/// How much process output to keep on disk, under ~/.launcho/logs/.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogsSpec {
+  /// How big a log file may get before it's rotated.
+  #[serde(default = "default_max_file_size")]
+  pub max_file_size: ByteSize,
+  /// How many log files to keep for each version, including the one being written.
+  #[serde(default = "default_max_files")]
+  pub max_files:     usize,
+  /// Log files that haven't been written to for this long are deleted.
+  #[serde(default = "default_logs_max_age")]
+  pub max_age:       HumanDuration,
+}
+
+fn default_max_file_size() -> ByteSize {
+  ByteSize(10 << 20)
+}
+
+fn default_max_files() -> usize {
+  5
+}
+
+fn default_logs_max_age() -> HumanDuration {
+  HumanDuration::from_secs(7 * 24 * 60 * 60)
+}
+
+impl Default for LogsSpec {
+  fn default() -> Self {
+    Self {
+      max_file_size: default_max_file_size(),
+      max_files:     default_max_files(),
+      max_age:       default_logs_max_age(),
+    }
+  }
 }
 
 impl ServerSpec {
@@ -38,7 +121,7 @@ impl Secrets {
   }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct SecretsSpec {
   pub file:   Option<String>,
@@ -46,16 +129,6 @@ pub struct SecretsSpec {
   pub values: Option<HashMap<String, String>>,
 }
 
-impl Default for SecretsSpec {
-  fn default() -> Self {
-    Self {
-      file:   None,
-      env:    None,
-      values: None,
-    }
-  }
-}
-
 impl SecretsSpec {
   pub fn load(&self) -> Result<Secrets, Error> {
     let mut secrets = HashMap::new();
@@ -120,8 +193,8 @@ macro_rules! get_extra_secrets {
 pub fn insert_and_save_secret(secrets: &mut Secrets, key: &str, value: &str) -> Result<(), Error> {
   let (extra_secrets_path, mut extra_secrets) = get_extra_secrets!();
   extra_secrets.insert(key.to_string(), value.to_string());
-  let file_contents = serde_yaml::to_string(&extra_secrets)
-    .with_context(|| format!("Failed to serialize extra secrets"))?;
+  let file_contents =
+    serde_yaml::to_string(&extra_secrets).context("Failed to serialize extra secrets")?;
   std::fs::write(&extra_secrets_path, file_contents)?;
   // Don't update the secrets locally until we successfully save it.
   secrets.0.insert(key.to_string(), value.to_string());
@@ -132,7 +205,7 @@ pub fn delete_extra_secrets(secrets: &mut Secrets, keys: &[String]) -> Result<St
   let mut message = String::new();
   let (extra_secrets_path, mut extra_secrets): (_, BTreeMap<String, String>) = get_extra_secrets!();
   for key in keys {
-    message.push_str(&key);
+    message.push_str(key);
     message.push_str(": ");
     message.push_str(match (
       secrets.0.remove(key).is_some(),
@@ -144,8 +217,8 @@ pub fn delete_extra_secrets(secrets: &mut Secrets, keys: &[String]) -> Result<St
       (false, false) => "Secret not found.\n",
     });
   }
-  let file_contents = serde_yaml::to_string(&extra_secrets)
-    .with_context(|| format!("Failed to serialize extra secrets"))?;
+  let file_contents =
+    serde_yaml::to_string(&extra_secrets).context("Failed to serialize extra secrets")?;
   std::fs::write(extra_secrets_path, file_contents)?;
   Ok(message)
 }
@@ -164,17 +237,501 @@ impl LaunchoConfig {
   }
 }
 
+/// How to check if a process is healthy, and how often.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
 pub struct HealthCheckSpec {
-  pub service: String,
-  pub path:    String,
+  #[serde(flatten)]
+  pub kind:              HealthCheckKind,
+  /// How long to wait between checks.
+  #[serde(default = "default_check_interval")]
+  pub interval:          HumanDuration,
+  /// How long a check may take before it counts as failed.
+  #[serde(default = "default_check_timeout")]
+  pub timeout:           HumanDuration,
+  /// How many checks in a row must fail for a running process to be considered unhealthy.
+  #[serde(default = "default_failure_threshold")]
+  pub failure_threshold: u32,
+  /// How many checks in a row must pass for a starting process to be considered running.
+  #[serde(default = "default_success_threshold")]
+  pub success_threshold: u32,
+  /// How long to wait after the process is spawned before the first check.
+  #[serde(default = "default_initial_delay")]
+  pub initial_delay:     HumanDuration,
+}
+
+fn default_check_interval() -> HumanDuration {
+  HumanDuration::from_secs(10)
+}
+
+fn default_check_timeout() -> HumanDuration {
+  HumanDuration::from_secs(5)
+}
+
+fn default_failure_threshold() -> u32 {
+  3
+}
+
+fn default_success_threshold() -> u32 {
+  1
+}
+
+fn default_initial_delay() -> HumanDuration {
+  HumanDuration::from_secs(0)
+}
+
+/// The kind of check to run. Without a type, a check is taken to be HTTP.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum HealthCheckKind {
+  /// Passes if a TCP connection to the service's port can be made.
+  Tcp {
+    service: String,
+  },
+  /// Passes if the command exits successfully. It runs in the process's cwd, with its env.
+  Exec {
+    command: Vec<String>,
+  },
+  Http(HttpCheckSpec),
+  /// Like HTTP, but over TLS, without verifying the certificate.
+  Https(HttpCheckSpec),
+  /// Goes by what the process sends over $NOTIFY_SOCKET, like with systemd. As a readiness check
+  /// this passes once READY=1 is sent, and as a liveness check it passes if WATCHDOG=1 has been
+  /// sent within the interval.
+  Notify {},
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpCheckSpec {
+  pub service:         String,
+  #[serde(default = "default_check_path")]
+  pub path:            String,
+  #[serde(default = "default_check_method")]
+  pub method:          String,
+  #[serde(default)]
+  pub headers:         BTreeMap<String, String>,
+  /// Overrides the Host header.
+  pub host:            Option<String>,
+  /// The statuses that count as healthy. If empty, any 2xx status does.
+  #[serde(default)]
+  pub expected_status: Vec<u16>,
+  /// If set, the response body must contain this.
+  pub body_contains:   Option<String>,
+}
+
+fn default_check_path() -> String {
+  "/".to_string()
+}
+
+fn default_check_method() -> String {
+  "GET".to_string()
+}
+
+/// Health checks used to just be HTTP checks, so we default the type for compatibility. A check
+/// may also be given as just its type, like "notify", to use the default options.
+fn deserialize_health_check<'de, D: serde::Deserializer<'de>>(
+  deserializer: D,
+) -> Result<Option<HealthCheckSpec>, D::Error> {
+  let Some(mut value) = Option::<serde_json::Value>::deserialize(deserializer)? else {
+    return Ok(None);
+  };
+  match &mut value {
+    serde_json::Value::Object(map) => {
+      map.entry("type").or_insert_with(|| "http".into());
+    }
+    serde_json::Value::String(kind) => value = serde_json::json!({ "type": kind }),
+    _ => {}
+  }
+  serde_json::from_value(value).map(Some).map_err(serde::de::Error::custom)
 }
 
 impl HealthCheckSpec {
+  pub fn apply_secrets(&mut self, secrets: &Secrets) -> Result<(), Error> {
+    self.kind.apply_secrets(secrets)
+  }
+}
+
+impl HealthCheckKind {
+  pub fn apply_secrets(&mut self, secrets: &Secrets) -> Result<(), Error> {
+    match self {
+      HealthCheckKind::Tcp { service } => *service = secrets.substitute(service)?,
+      HealthCheckKind::Exec { command } =>
+        for arg in command {
+          *arg = secrets.substitute(arg)?;
+        },
+      HealthCheckKind::Http(http) | HealthCheckKind::Https(http) => http.apply_secrets(secrets)?,
+      HealthCheckKind::Notify {} => {}
+    }
+    Ok(())
+  }
+
+  /// The service whose port the check connects to, if any.
+  pub fn service(&self) -> Option<&str> {
+    match self {
+      HealthCheckKind::Tcp { service } => Some(service),
+      HealthCheckKind::Exec { .. } | HealthCheckKind::Notify {} => None,
+      HealthCheckKind::Http(http) | HealthCheckKind::Https(http) => Some(&http.service),
+    }
+  }
+}
+
+impl HttpCheckSpec {
   pub fn apply_secrets(&mut self, secrets: &Secrets) -> Result<(), Error> {
     self.service = secrets.substitute(&self.service)?;
     self.path = secrets.substitute(&self.path)?;
+    for value in self.headers.values_mut() {
+      *value = secrets.substitute(value)?;
+    }
+    if let Some(host) = &mut self.host {
+      *host = secrets.substitute(host)?;
+    }
+    Ok(())
+  }
+}
+
+/// A duration written like "500ms", "30s", "10m", "2h" or "1d". A bare number means seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HumanDuration(pub std::time::Duration);
+
+impl HumanDuration {
+  pub fn from_secs(secs: u64) -> Self {
+    Self(std::time::Duration::from_secs(secs))
+  }
+}
+
+pub fn parse_duration(s: &str) -> Result<std::time::Duration, Error> {
+  let s = s.trim();
+  let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+  let (number, unit) = s.split_at(split);
+  let number: u64 = number.parse().with_context(|| format!("Invalid duration {:?}", s))?;
+  let millis_per_unit = match unit.trim() {
+    "ms" => 1,
+    "" | "s" => 1_000,
+    "m" => 60_000,
+    "h" => 60 * 60_000,
+    "d" => 24 * 60 * 60_000,
+    _ => bail!("Invalid duration {:?} -- expected a unit of ms, s, m, h or d", s),
+  };
+  let millis = number
+    .checked_mul(millis_per_unit)
+    .ok_or_else(|| anyhow!("Duration {:?} is too long", s))?;
+  Ok(std::time::Duration::from_millis(millis))
+}
+
+impl Serialize for HumanDuration {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let millis = self.0.as_millis();
+    match millis % 1000 {
+      0 => serializer.serialize_str(&format!("{}s", millis / 1000)),
+      _ => serializer.serialize_str(&format!("{}ms", millis)),
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum SecondsOrString {
+      Seconds(u64),
+      String(String),
+    }
+    Ok(match SecondsOrString::deserialize(deserializer)? {
+      SecondsOrString::Seconds(secs) => Self::from_secs(secs),
+      SecondsOrString::String(s) => Self(parse_duration(&s).map_err(serde::de::Error::custom)?),
+    })
+  }
+}
+
+/// A size written like "512M" or "2G", using powers of 1024. A bare number means bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ByteSize(pub u64);
+
+pub fn parse_byte_size(s: &str) -> Result<u64, Error> {
+  let s = s.trim();
+  let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+  let (number, unit) = s.split_at(split);
+  let number: u64 = number.parse().with_context(|| format!("Invalid size {:?}", s))?;
+  let multiplier: u64 = match unit.trim().trim_end_matches(['i', 'B']) {
+    "" => 1,
+    "K" | "k" => 1 << 10,
+    "M" => 1 << 20,
+    "G" => 1 << 30,
+    "T" => 1 << 40,
+    _ => bail!("Invalid size {:?} -- expected a unit of K, M, G or T", s),
+  };
+  number.checked_mul(multiplier).ok_or_else(|| anyhow!("Size {:?} is too large", s))
+}
+
+impl Serialize for ByteSize {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(self.0)
+  }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BytesOrString {
+      Bytes(u64),
+      String(String),
+    }
+    Ok(match BytesOrString::deserialize(deserializer)? {
+      BytesOrString::Bytes(bytes) => Self(bytes),
+      BytesOrString::String(s) => Self(parse_byte_size(&s).map_err(serde::de::Error::custom)?),
+    })
+  }
+}
+
+/// A CPU allowance, written as a number of CPUs like 1.5, or in millicpus like "1500m".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CpuLimit {
+  pub millicpus: u64,
+}
+
+impl Serialize for CpuLimit {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{}m", self.millicpus))
+  }
+}
+
+impl<'de> Deserialize<'de> for CpuLimit {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum CpusOrString {
+      Cpus(f64),
+      String(String),
+    }
+    let millicpus = match CpusOrString::deserialize(deserializer)? {
+      CpusOrString::Cpus(cpus) => (cpus * 1000.0).round(),
+      CpusOrString::String(s) => match s.trim().strip_suffix('m') {
+        Some(millicpus) => millicpus.parse::<f64>(),
+        None => s.trim().parse::<f64>().map(|cpus| cpus * 1000.0),
+      }
+      .map_err(|_| serde::de::Error::custom(format!("Invalid cpu limit {:?}", s)))?
+      .round(),
+    };
+    if millicpus.is_nan() || millicpus < 1.0 {
+      return Err(serde::de::Error::custom("cpu limit must be at least 1m"));
+    }
+    Ok(Self {
+      millicpus: millicpus as u64,
+    })
+  }
+}
+
+/// Resource limits, enforced by putting the process in its own cgroup.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsSpec {
+  /// The most memory the process (and its children) may use before being OOM killed.
+  pub memory:    Option<ByteSize>,
+  pub cpu:       Option<CpuLimit>,
+  /// The most processes and threads that may exist at once.
+  pub pids:      Option<u64>,
+  /// The relative weight for IO, from 1 to 10000, where the default is 100.
+  pub io_weight: Option<u16>,
+}
+
+impl LimitsSpec {
+  pub fn is_empty(&self) -> bool {
+    *self == Self::default()
+  }
+}
+
+/// Opt-in, container-like isolation. The process gets its own mount, PID, IPC and UTS namespaces,
+/// but shares the host's network, so that it can still receive traffic on its loopback ports.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IsolationSpec {
+  /// Mount a fresh tmpfs on /tmp.
+  #[serde(default = "default_true")]
+  pub private_tmp:    bool,
+  /// Make every mount read-only, apart from /tmp, /dev, /proc, /sys, and the writable paths.
+  #[serde(default = "default_true")]
+  pub read_only_root: bool,
+  /// Paths that stay writable, on top of the cwd and resources.
+  #[serde(default)]
+  pub writable:       Vec<String>,
+  pub hostname:       Option<String>,
+}
+
+fn default_true() -> bool {
+  true
+}
+
+impl IsolationSpec {
+  pub fn apply_secrets(&mut self, secrets: &Secrets) -> Result<(), Error> {
+    for path in &mut self.writable {
+      *path = secrets.substitute(path)?;
+    }
+    if let Some(hostname) = &mut self.hostname {
+      *hostname = secrets.substitute(hostname)?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShutdownSpec {
+  /// How long to wait for open connections to close after traffic is moved away.
+  #[serde(default = "default_drain_timeout")]
+  pub drain_timeout: HumanDuration,
+  /// The signal sent once draining is done, like "SIGTERM" or "SIGINT".
+  #[serde(default = "default_stop_signal")]
+  pub stop_signal:   String,
+  /// How long to wait after the stop signal before sending SIGKILL.
+  #[serde(default = "default_kill_after")]
+  pub kill_after:    HumanDuration,
+}
+
+fn default_drain_timeout() -> HumanDuration {
+  HumanDuration::from_secs(30)
+}
+
+fn default_stop_signal() -> String {
+  "SIGTERM".to_string()
+}
+
+fn default_kill_after() -> HumanDuration {
+  HumanDuration::from_secs(30)
+}
+
+impl Default for ShutdownSpec {
+  fn default() -> Self {
+    Self {
+      drain_timeout: default_drain_timeout(),
+      stop_signal:   default_stop_signal(),
+      kill_after:    default_kill_after(),
+    }
+  }
+}
+
+/// Shell commands run around a version's lifetime, in its cwd and with its env and service ports.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HooksSpec {
+  /// Run in the background once the version is running.
+  pub post_start: Option<String>,
+  /// Run once the version's connections have drained, before the stop signal is sent. The stop
+  /// signal gets sent even if it fails.
+  pub pre_stop:   Option<String>,
+  /// How long each hook may take before it's killed.
+  #[serde(default = "default_hook_timeout")]
+  pub timeout:    HumanDuration,
+}
+
+fn default_hook_timeout() -> HumanDuration {
+  HumanDuration::from_secs(60)
+}
+
+impl Default for HooksSpec {
+  fn default() -> Self {
+    Self {
+      post_start: None,
+      pre_stop:   None,
+      timeout:    default_hook_timeout(),
+    }
+  }
+}
+
+impl HooksSpec {
+  pub fn apply_secrets(&mut self, secrets: &Secrets) -> Result<(), Error> {
+    for hook in [&mut self.post_start, &mut self.pre_stop].into_iter().flatten() {
+      *hook = secrets.substitute(hook)?;
+    }
+    Ok(())
+  }
+}
+
+/// When to relaunch a process that has exited on its own.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+  #[default]
+  Always,
+  /// Only relaunch if the process exited with a non-zero status, or failed to start.
+  OnFailure,
+  Never,
+}
+
+/// How long to wait before relaunching a process. Each relaunch in a row waits `multiplier` times
+/// longer than the last, up to `max`, until a process stays up for `reset_after`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackoffSpec {
+  #[serde(default = "default_backoff_initial")]
+  pub initial:     HumanDuration,
+  #[serde(default = "default_backoff_max")]
+  pub max:         HumanDuration,
+  #[serde(default = "default_backoff_multiplier")]
+  pub multiplier:  f64,
+  #[serde(default = "default_backoff_reset_after")]
+  pub reset_after: HumanDuration,
+}
+
+fn default_backoff_initial() -> HumanDuration {
+  HumanDuration::from_secs(1)
+}
+
+fn default_backoff_max() -> HumanDuration {
+  HumanDuration::from_secs(20 * 60)
+}
+
+fn default_backoff_multiplier() -> f64 {
+  2.0
+}
+
+fn default_backoff_reset_after() -> HumanDuration {
+  HumanDuration::from_secs(10 * 60)
+}
+
+impl Default for BackoffSpec {
+  fn default() -> Self {
+    Self {
+      initial:     default_backoff_initial(),
+      max:         default_backoff_max(),
+      multiplier:  default_backoff_multiplier(),
+      reset_after: default_backoff_reset_after(),
+    }
+  }
+}
+
+impl BackoffSpec {
+  /// How long to wait before a relaunch, given how many relaunches in a row came before it.
+  pub fn delay(&self, previous_restarts: u32) -> std::time::Duration {
+    let secs = self.initial.0.as_secs_f64() * self.multiplier.powi(previous_restarts as i32);
+    std::time::Duration::from_secs_f64(secs.min(self.max.0.as_secs_f64()))
+  }
+}
+
+// f64 isn't Eq or Hash, so we compare the multiplier by its bits.
+impl PartialEq for BackoffSpec {
+  fn eq(&self, other: &Self) -> bool {
+    self.initial == other.initial
+      && self.max == other.max
+      && self.multiplier.to_bits() == other.multiplier.to_bits()
+      && self.reset_after == other.reset_after
+  }
+}
+
+impl Eq for BackoffSpec {}
+
+impl std::hash::Hash for BackoffSpec {
+  fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+    self.initial.hash(state);
+    self.max.hash(state);
+    self.multiplier.to_bits().hash(state);
+    self.reset_after.hash(state);
+  }
+}
+
+impl ShutdownSpec {
+  pub fn apply_secrets(&mut self, secrets: &Secrets) -> Result<(), Error> {
+    self.stop_signal = secrets.substitute(&self.stop_signal)?;
     Ok(())
   }
 }
@@ -188,10 +745,10 @@ pub enum UidOrUsername {
 
 impl UidOrUsername {
   pub fn apply_secrets(&mut self, secrets: &Secrets) -> Result<(), Error> {
-    Ok(match self {
-      UidOrUsername::Uid(_) => {}
-      UidOrUsername::Username(s) => *s = secrets.substitute(s)?,
-    })
+    if let UidOrUsername::Username(s) = self {
+      *s = secrets.substitute(s)?;
+    }
+    Ok(())
   }
 
   pub fn to_uid(&self) -> Result<u32, Error> {
@@ -204,6 +761,17 @@ impl UidOrUsername {
       }
     })
   }
+
+  pub fn to_gid(&self) -> Result<u32, Error> {
+    Ok(match self {
+      UidOrUsername::Uid(gid) => *gid,
+      UidOrUsername::Username(group_name) => {
+        let group = users::get_group_by_name(group_name)
+          .with_context(|| format!("Failed to find group {}", group_name))?;
+        group.gid()
+      }
+    })
+  }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -221,22 +789,65 @@ impl ResourceRequest {
   }
 }
 
+#[serde_with::serde_as]
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ProcessSpec {
-  pub name:      String,
-  pub cwd:       Option<String>,
+  pub name:            String,
+  pub cwd:             Option<String>,
+  #[serde(default)]
+  pub resources:       Vec<ResourceRequest>,
+  /// Shell commands run in order before launching, either as a single string or a list of steps.
+  #[serde_as(as = "serde_with::OneOrMany<_, serde_with::formats::PreferOne>")]
+  #[serde(default)]
+  pub before:          Vec<String>,
+  pub command:         Vec<String>,
+  #[serde(default)]
+  pub env:             BTreeMap<String, String>,
+  #[serde(default)]
+  pub receives:        Vec<String>,
+  /// Must pass before the process gets traffic, and gets the process replaced when it fails.
+  #[serde(default, deserialize_with = "deserialize_health_check")]
+  pub health:          Option<HealthCheckSpec>,
+  /// Like health, but failing only takes the process out of rotation until it passes again.
+  #[serde(default, deserialize_with = "deserialize_health_check")]
+  pub readiness:       Option<HealthCheckSpec>,
+  /// Gets the process replaced when it fails, but doesn't hold up sending it traffic.
+  #[serde(default, deserialize_with = "deserialize_health_check")]
+  pub liveness:        Option<HealthCheckSpec>,
+  pub uid:             Option<UidOrUsername>,
+  pub gid:             Option<UidOrUsername>,
+  /// How many copies of this process to keep running.
+  #[serde(default = "default_replicas")]
+  pub replicas:        usize,
+  #[serde(default)]
+  pub shutdown:        ShutdownSpec,
+  #[serde(default)]
+  pub hooks:           HooksSpec,
+  /// How long copying resources and running the before command may take.
+  #[serde(default = "default_prepare_timeout")]
+  pub prepare_timeout: HumanDuration,
+  /// How long the process may take to become ready once spawned, before it's killed. By default
+  /// it may take forever.
+  pub startup_timeout: Option<HumanDuration>,
+  #[serde(default)]
+  pub restart:         RestartPolicy,
   #[serde(default)]
-  pub resources: Vec<ResourceRequest>,
-  pub before:    Option<String>,
-  pub command:   Vec<String>,
+  pub backoff:         BackoffSpec,
+  /// Names of processes that must be running before this one is launched.
   #[serde(default)]
-  pub env:       BTreeMap<String, String>,
+  pub depends_on:      Vec<String>,
   #[serde(default)]
-  pub receives:  Vec<String>,
-  pub health:    Option<HealthCheckSpec>,
-  pub uid:       Option<UidOrUsername>,
-  pub gid:       Option<UidOrUsername>,
+  pub limits:          LimitsSpec,
+  pub isolation:       Option<IsolationSpec>,
+}
+
+fn default_replicas() -> usize {
+  1
+}
+
+fn default_prepare_timeout() -> HumanDuration {
+  HumanDuration::from_secs(10 * 60)
 }
 
 impl ProcessSpec {
@@ -248,7 +859,7 @@ impl ProcessSpec {
     for resource in &mut self.resources {
       resource.apply_secrets(secrets)?;
     }
-    if let Some(before) = &mut self.before {
+    for before in &mut self.before {
       *before = secrets.substitute(before)?;
     }
     for command in &mut self.command {
@@ -260,8 +871,84 @@ impl ProcessSpec {
     for receive in &mut self.receives {
       *receive = secrets.substitute(receive)?;
     }
-    if let Some(health) = &mut self.health {
-      health.apply_secrets(secrets)?;
+    for dependency in &mut self.depends_on {
+      *dependency = secrets.substitute(dependency)?;
+    }
+    for check in [&mut self.health, &mut self.readiness, &mut self.liveness].into_iter().flatten() {
+      check.apply_secrets(secrets)?;
+    }
+    if let Some(uid) = &mut self.uid {
+      uid.apply_secrets(secrets)?;
+    }
+    if let Some(gid) = &mut self.gid {
+      gid.apply_secrets(secrets)?;
+    }
+    if let Some(isolation) = &mut self.isolation {
+      isolation.apply_secrets(secrets)?;
+    }
+    self.shutdown.apply_secrets(secrets)?;
+    self.hooks.apply_secrets(secrets)?;
+    Ok(())
+  }
+
+  /// Checks if two specs describe the same version of a process, ignoring the replica count.
+  /// Scaling a process up or down shouldn't cause a rolling upgrade of its existing replicas.
+  pub fn is_same_version(&self, other: &ProcessSpec) -> bool {
+    let normalized = ProcessSpec {
+      replicas: other.replicas,
+      ..self.clone()
+    };
+    normalized == *other
+  }
+}
+
+/// A run-to-completion task. Jobs are launched like processes, but aren't relaunched when they
+/// exit, and don't receive traffic.
+#[serde_with::serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobSpec {
+  pub name:            String,
+  pub cwd:             Option<String>,
+  #[serde(default)]
+  pub resources:       Vec<ResourceRequest>,
+  #[serde_as(as = "serde_with::OneOrMany<_, serde_with::formats::PreferOne>")]
+  #[serde(default)]
+  pub before:          Vec<String>,
+  pub command:         Vec<String>,
+  #[serde(default)]
+  pub env:             BTreeMap<String, String>,
+  pub uid:             Option<UidOrUsername>,
+  pub gid:             Option<UidOrUsername>,
+  #[serde(default = "default_prepare_timeout")]
+  pub prepare_timeout: HumanDuration,
+  #[serde(default)]
+  pub limits:          LimitsSpec,
+  pub isolation:       Option<IsolationSpec>,
+  /// A cron-style schedule, like "0 3 * * *", evaluated in the server's local time.
+  pub schedule:        Option<String>,
+  /// Whether to run the job whenever it changes (or the server starts).
+  /// Defaults to true for jobs without a schedule, and false otherwise.
+  pub on_change:       Option<bool>,
+}
+
+impl JobSpec {
+  pub fn apply_secrets(&mut self, secrets: &Secrets) -> Result<(), Error> {
+    self.name = secrets.substitute(&self.name)?;
+    if let Some(cwd) = &mut self.cwd {
+      *cwd = secrets.substitute(cwd)?;
+    }
+    for resource in &mut self.resources {
+      resource.apply_secrets(secrets)?;
+    }
+    for before in &mut self.before {
+      *before = secrets.substitute(before)?;
+    }
+    for command in &mut self.command {
+      *command = secrets.substitute(command)?;
+    }
+    for env in self.env.values_mut() {
+      *env = secrets.substitute(env)?;
     }
     if let Some(uid) = &mut self.uid {
       uid.apply_secrets(secrets)?;
@@ -269,8 +956,45 @@ impl ProcessSpec {
     if let Some(gid) = &mut self.gid {
       gid.apply_secrets(secrets)?;
     }
+    if let Some(isolation) = &mut self.isolation {
+      isolation.apply_secrets(secrets)?;
+    }
+    if let Some(schedule) = &mut self.schedule {
+      *schedule = secrets.substitute(schedule)?;
+    }
     Ok(())
   }
+
+  pub fn runs_on_change(&self) -> bool {
+    self.on_change.unwrap_or(self.schedule.is_none())
+  }
+
+  pub fn to_process_spec(&self) -> ProcessSpec {
+    ProcessSpec {
+      name:            self.name.clone(),
+      cwd:             self.cwd.clone(),
+      resources:       self.resources.clone(),
+      before:          self.before.clone(),
+      command:         self.command.clone(),
+      env:             self.env.clone(),
+      receives:        Vec::new(),
+      health:          None,
+      readiness:       None,
+      liveness:        None,
+      uid:             self.uid.clone(),
+      gid:             self.gid.clone(),
+      replicas:        1,
+      shutdown:        ShutdownSpec::default(),
+      hooks:           HooksSpec::default(),
+      prepare_timeout: self.prepare_timeout,
+      startup_timeout: None,
+      restart:         RestartPolicy::default(),
+      backoff:         BackoffSpec::default(),
+      depends_on:      Vec::new(),
+      limits:          self.limits.clone(),
+      isolation:       self.isolation.clone(),
+    }
+  }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -293,6 +1017,8 @@ impl ServiceSpec {
 pub struct LaunchoTarget {
   pub services:  Vec<ServiceSpec>,
   pub processes: Vec<ProcessSpec>,
+  #[serde(default)]
+  pub jobs:      Vec<JobSpec>,
 }
 
 impl LaunchoTarget {
@@ -303,6 +1029,9 @@ impl LaunchoTarget {
     for service in &mut self.services {
       service.apply_secrets(secrets)?;
     }
+    for job in &mut self.jobs {
+      job.apply_secrets(secrets)?;
+    }
     Ok(())
   }
 }
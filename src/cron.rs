@@ -0,0 +1,250 @@
+use anyhow::{bail, Context, Error};
+
+/// A cron-style schedule, with the usual five fields: minute, hour, day of month, month, and day
+/// of week. Each field can be `*`, a number, a range like `1-5`, a step like `*/15` or `0-30/10`,
+/// or a comma separated list of those.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+  minutes:       Vec<bool>,
+  hours:         Vec<bool>,
+  days_of_month: Vec<bool>,
+  months:        Vec<bool>,
+  days_of_week:  Vec<bool>,
+  /// Like standard cron, if both day fields are restricted then either may match.
+  days_either:   bool,
+}
+
+/// The fields of a local time that a schedule is matched against.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeFields {
+  pub minute:       u32,
+  pub hour:         u32,
+  /// 1 to 31.
+  pub day_of_month: u32,
+  /// 1 to 12.
+  pub month:        u32,
+  /// 0 to 6, with 0 being Sunday.
+  pub day_of_week:  u32,
+}
+
+impl TimeFields {
+  pub fn from_local_time(unix_time: u64) -> Self {
+    let time = unix_time as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&time, &mut tm) };
+    Self {
+      minute:       tm.tm_min as u32,
+      hour:         tm.tm_hour as u32,
+      day_of_month: tm.tm_mday as u32,
+      month:        tm.tm_mon as u32 + 1,
+      day_of_week:  tm.tm_wday as u32,
+    }
+  }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>, Error> {
+  let mut allowed = vec![false; max as usize + 1];
+  for part in field.split(',') {
+    let (range, step) = match part.split_once('/') {
+      Some((range, step)) =>
+        (range, step.parse::<u32>().with_context(|| format!("bad step {:?}", step))?),
+      None => (part, 1),
+    };
+    if step == 0 {
+      bail!("step can't be zero in {:?}", part);
+    }
+    let (start, end) = match range {
+      "*" => (min, max),
+      _ => match range.split_once('-') {
+        Some((start, end)) => (start.parse()?, end.parse()?),
+        None => {
+          let value = range.parse().with_context(|| format!("bad value {:?}", range))?;
+          // Like cron, "5/10" means starting at 5 and going until the end.
+          (value, if part.contains('/') { max } else { value })
+        }
+      },
+    };
+    if start < min || end > max || start > end {
+      bail!("{:?} is out of range {}-{}", part, min, max);
+    }
+    for value in (start..=end).step_by(step as usize) {
+      allowed[value as usize] = true;
+    }
+  }
+  Ok(allowed)
+}
+
+impl Schedule {
+  pub fn parse(spec: &str) -> Result<Self, Error> {
+    let fields: Vec<&str> = spec.split_whitespace().collect();
+    let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+      bail!("Schedule {:?} should have five fields: minute hour day month weekday", spec);
+    };
+    let context = || format!("Invalid schedule {:?}", spec);
+    // Like cron, a day field counts as restricted unless it starts with "*", so "*/2" doesn't.
+    let days_either = !days_of_month.starts_with('*') && !days_of_week.starts_with('*');
+    let mut days_of_week = parse_field(days_of_week, 0, 7).with_context(context)?;
+    // Both 0 and 7 mean Sunday.
+    if days_of_week[7] {
+      days_of_week[0] = true;
+    }
+    days_of_week.truncate(7);
+    Ok(Self {
+      minutes: parse_field(minutes, 0, 59).with_context(context)?,
+      hours: parse_field(hours, 0, 23).with_context(context)?,
+      days_of_month: parse_field(days_of_month, 1, 31).with_context(context)?,
+      months: parse_field(months, 1, 12).with_context(context)?,
+      days_of_week,
+      days_either,
+    })
+  }
+
+  pub fn matches(&self, time: &TimeFields) -> bool {
+    let day_of_month = self.days_of_month[time.day_of_month as usize];
+    let day_of_week = self.days_of_week[time.day_of_week as usize];
+    let day = match self.days_either {
+      true => day_of_month || day_of_week,
+      false => day_of_month && day_of_week,
+    };
+    self.minutes[time.minute as usize]
+      && self.hours[time.hour as usize]
+      && self.months[time.month as usize]
+      && day
+  }
+
+  /// Finds the first of some minutes (counting from the epoch) that the schedule fires in, given
+  /// how to get the time fields of a minute.
+  pub fn first_fire(
+    &self,
+    minutes: std::ops::RangeInclusive<u64>,
+    time_fields: impl Fn(u64) -> TimeFields,
+  ) -> Option<u64> {
+    minutes.into_iter().find(|&minute| self.matches(&time_fields(minute)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// 2024-01-01T00:00:00Z, a Monday.
+  const START_MINUTE: u64 = 1704067200 / 60;
+
+  fn utc_fields(minute: u64) -> TimeFields {
+    let time = (minute * 60) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::gmtime_r(&time, &mut tm) };
+    TimeFields {
+      minute:       tm.tm_min as u32,
+      hour:         tm.tm_hour as u32,
+      day_of_month: tm.tm_mday as u32,
+      month:        tm.tm_mon as u32 + 1,
+      day_of_week:  tm.tm_wday as u32,
+    }
+  }
+
+  fn allowed(values: &[bool]) -> Vec<usize> {
+    values
+      .iter()
+      .enumerate()
+      .filter(|(_, allowed)| **allowed)
+      .map(|(value, _)| value)
+      .collect()
+  }
+
+  /// Gives the next few times the schedule fires, as "month-day hour:minute" in UTC.
+  fn next_fires(spec: &str, count: usize) -> Vec<String> {
+    let schedule = Schedule::parse(spec).unwrap();
+    let mut fires = Vec::new();
+    let mut after = START_MINUTE;
+    for _ in 0..count {
+      let minute = schedule.first_fire(after..=after + 4 * 366 * 24 * 60, utc_fields).unwrap();
+      let fields = utc_fields(minute);
+      fires.push(format!(
+        "{:02}-{:02} {:02}:{:02}",
+        fields.month, fields.day_of_month, fields.hour, fields.minute
+      ));
+      after = minute + 1;
+    }
+    fires
+  }
+
+  #[test]
+  fn parses_fields() {
+    let schedule = Schedule::parse("*/15 9-17/4 1,15,20-21 * 1-5").unwrap();
+    assert_eq!(allowed(&schedule.minutes), [0, 15, 30, 45]);
+    assert_eq!(allowed(&schedule.hours), [9, 13, 17]);
+    assert_eq!(allowed(&schedule.days_of_month), [1, 15, 20, 21]);
+    assert_eq!(allowed(&schedule.months), (1..=12).collect::<Vec<_>>());
+    assert_eq!(allowed(&schedule.days_of_week), [1, 2, 3, 4, 5]);
+    assert!(schedule.days_either);
+
+    // A bare start with a step runs to the end of the range.
+    assert_eq!(allowed(&Schedule::parse("5/20 * * * *").unwrap().minutes), [5, 25, 45]);
+    // Both 0 and 7 mean Sunday.
+    assert_eq!(allowed(&Schedule::parse("0 0 * * 5-7").unwrap().days_of_week), [0, 5, 6]);
+  }
+
+  #[test]
+  fn rejects_bad_schedules() {
+    for spec in [
+      "* * * *",
+      "* * * * * *",
+      "60 * * * *",
+      "* 24 * * *",
+      "* * 0 * *",
+      "* * * 13 *",
+      "* * * * 8",
+      "*/0 * * * *",
+      "5-1 * * * *",
+      "a * * * *",
+      "*/x * * * *",
+    ] {
+      assert!(Schedule::parse(spec).is_err(), "{:?} should be rejected", spec);
+    }
+  }
+
+  #[test]
+  fn day_fields_combine_like_cron() {
+    // Both restricted, so either may match.
+    assert!(Schedule::parse("0 0 1 * 1").unwrap().days_either);
+    // Starting with "*" doesn't count as restricted, even with a step.
+    assert!(!Schedule::parse("0 0 * * 1").unwrap().days_either);
+    assert!(!Schedule::parse("0 0 */2 * 1").unwrap().days_either);
+    assert!(!Schedule::parse("0 0 1 * */2").unwrap().days_either);
+  }
+
+  #[test]
+  fn finds_next_fires() {
+    assert_eq!(next_fires("*/15 * * * *", 3), ["01-01 00:00", "01-01 00:15", "01-01 00:30"]);
+    assert_eq!(next_fires("30 9 * * 1-5", 6), [
+      "01-01 09:30",
+      "01-02 09:30",
+      "01-03 09:30",
+      "01-04 09:30",
+      "01-05 09:30",
+      "01-08 09:30",
+    ]);
+    // The 1st of the month, or any Sunday.
+    assert_eq!(next_fires("0 12 1 * 0", 4), [
+      "01-01 12:00",
+      "01-07 12:00",
+      "01-14 12:00",
+      "01-21 12:00"
+    ]);
+    // Odd days that are also Mondays, as "*/2" doesn't count as restricting the day.
+    assert_eq!(next_fires("0 0 */2 * 1", 3), ["01-01 00:00", "01-15 00:00", "01-29 00:00"]);
+    // 2024 is a leap year.
+    assert_eq!(next_fires("0 0 29 2 *", 1), ["02-29 00:00"]);
+  }
+
+  #[test]
+  fn first_fire_stays_within_range() {
+    let schedule = Schedule::parse("0 * * * *").unwrap();
+    assert_eq!(schedule.first_fire(START_MINUTE + 1..=START_MINUTE + 59, utc_fields), None);
+    assert_eq!(
+      schedule.first_fire(START_MINUTE + 1..=START_MINUTE + 60, utc_fields),
+      Some(START_MINUTE + 60)
+    );
+  }
+}
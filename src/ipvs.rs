@@ -71,7 +71,7 @@ pub fn get_ipvs_state() -> Result<IpvsState, Error> {
     }
   }
   let mut current_service = None;
-  while let Some(line) = lines.next() {
+  for line in lines {
     // Split the line into components.
     let mut components = line.split_whitespace();
     let first = components.next().ok_or_else(short_output)?;
@@ -138,6 +138,29 @@ pub fn create_service(service: &ServiceSpec) -> Result<(), Error> {
   Ok(())
 }
 
+/// Sets a loopback real server's weight to zero without deleting it, so that no new connections
+/// are routed to it, but its existing connections still show up in the IPVS state.
+pub fn drain_loopback(service: &ServiceSpec, port: u16) -> Result<(), Error> {
+  let mut cmd = std::process::Command::new("ipvsadm");
+  cmd
+    .arg("--edit-server")
+    .arg("--tcp-service")
+    .arg(&service.on)
+    .arg("--real-server")
+    .arg(format!("localhost:{}", port))
+    .arg("--weight")
+    .arg("0")
+    .arg("--masquerading");
+  let output = cmd.output()?;
+  if !output.status.success() {
+    if std::str::from_utf8(&output.stderr)?.contains("No such destination") {
+      return Ok(());
+    }
+    bail!("Failed to run {:?}: {:?}", cmd, output);
+  }
+  Ok(())
+}
+
 pub fn set_loopback_weight(service: &ServiceSpec, port: u16, weight: i32) -> Result<(), Error> {
   // If the new weight is zero, simply delete it.
   if weight == 0 {
@@ -147,7 +170,7 @@ pub fn set_loopback_weight(service: &ServiceSpec, port: u16, weight: i32) -> Res
       .arg("--tcp-service")
       .arg(&service.on)
       .arg("--real-server")
-      .arg(&format!("localhost:{}", port));
+      .arg(format!("localhost:{}", port));
     let output = cmd.output()?;
     if !output.status.success() {
       if std::str::from_utf8(&output.stderr)?.contains("No such destination") {
@@ -165,9 +188,9 @@ pub fn set_loopback_weight(service: &ServiceSpec, port: u16, weight: i32) -> Res
         .arg("--tcp-service")
         .arg(&service.on)
         .arg("--real-server")
-        .arg(&format!("localhost:{}", port))
+        .arg(format!("localhost:{}", port))
         .arg("--weight")
-        .arg(&format!("{}", weight))
+        .arg(format!("{}", weight))
         .arg("--masquerading"),
     ) {
       Ok(_) => break,
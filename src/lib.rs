@@ -1,6 +1,11 @@
+#[cfg(target_os = "linux")]
+pub mod cgroup;
 pub mod config;
+pub mod cron;
 pub mod ipvs;
 #[cfg(target_os = "linux")]
+pub mod sandbox;
+#[cfg(target_os = "linux")]
 pub mod server;
 #[cfg(target_os = "linux")]
 pub mod storage;
@@ -23,11 +28,142 @@ fn make_cryptographic_token() -> String {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProcessStatus {
+  Preparing,
   Starting,
   Running,
   Unhealthy,
   Sunsetting,
-  Exited { exit_status: i32, approx_time: u64 },
+  Exited {
+    exit_status: i32,
+    approx_time: u64,
+    #[serde(default)]
+    reason:      ExitReason,
+  },
+  /// The process never became ready, and was killed.
+  FailedToStart {
+    reason:      StartFailure,
+    approx_time: u64,
+  },
+}
+
+impl ProcessStatus {
+  /// When the process stopped, if it's done for good, either by exiting or failing to start.
+  pub fn finished_time(&self) -> Option<u64> {
+    match self {
+      ProcessStatus::Exited { approx_time, .. }
+      | ProcessStatus::FailedToStart { approx_time, .. } => Some(*approx_time),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StartFailure {
+  /// The readiness check didn't pass within the startup timeout.
+  Timeout { after_secs: u64 },
+}
+
+impl std::fmt::Display for StartFailure {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      StartFailure::Timeout { after_secs } =>
+        write!(f, "not ready within the startup timeout of {}s", after_secs),
+    }
+  }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExitReason {
+  #[default]
+  Normal,
+  /// The process's cgroup hit its memory limit, and got OOM killed.
+  OutOfMemory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+  Stdout,
+  Stderr,
+}
+
+/// Which lines of a process's output to get, and how to show them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogOptions {
+  /// Prefix each line with when it was received.
+  #[serde(default)]
+  pub timestamps:  bool,
+  #[serde(default)]
+  pub stderr_only: bool,
+  /// Only get lines received within this many seconds.
+  pub since_secs:  Option<u64>,
+  /// Only get this many lines from the end.
+  pub tail:        Option<usize>,
+}
+
+/// Which versions of a process to get output from, when it's named by its spec.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogVersions {
+  /// The newest version.
+  #[default]
+  Current,
+  /// The version before the newest one, in the same replica slot.
+  Previous,
+  /// Every version we still have, combined.
+  All,
+}
+
+/// A line of output that matched a log search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogMatch {
+  pub process_name: String,
+  /// The name of the version the line came from.
+  pub name:         String,
+  pub time_ms:      u64,
+  pub stream:       OutputStream,
+  pub text:         String,
+}
+
+/// What we know about a process that exited unexpectedly.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+  pub name:          String,
+  pub process_name:  String,
+  /// The exit code, or -1 if the process was killed by a signal or we don't know.
+  pub exit_status:   i32,
+  pub signal:        Option<i32>,
+  pub core_dumped:   bool,
+  pub approx_time:   u64,
+  pub run_time_secs: u64,
+  /// The end of the process's output.
+  pub output_tail:   String,
+}
+
+// Crash reports go in events, which get printed in full, so we leave out the output.
+impl std::fmt::Debug for CrashReport {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("CrashReport")
+      .field("exit_status", &self.exit_status)
+      .field("signal", &self.signal)
+      .field("core_dumped", &self.core_dumped)
+      .field("run_time_secs", &self.run_time_secs)
+      .field("output_bytes", &self.output_tail.len())
+      .finish()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobTrigger {
+  Change,
+  Schedule,
+  Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LifecycleHook {
+  PostStart,
+  PreStop,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,11 +184,18 @@ pub enum LogEvent {
   LaunchProcess {
     name:             String,
     process_name:     String,
+    replica:          usize,
     port_allocations: HashMap<String, u16>,
   },
   StatusChange {
-    name:   String,
-    status: ProcessStatus,
+    name:         String,
+    status:       ProcessStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    crash_report: Option<CrashReport>,
+  },
+  ReadinessChange {
+    name:  String,
+    ready: bool,
   },
   Kill {
     name: String,
@@ -60,6 +203,53 @@ pub enum LogEvent {
   ForceRestart {
     name: String,
   },
+  RestartBackoff {
+    process_name: String,
+    replica:      usize,
+    delay_ms:     u64,
+  },
+  HookFinished {
+    name:    String,
+    hook:    LifecycleHook,
+    success: bool,
+    result:  String,
+  },
+  Draining {
+    name:             String,
+    open_connections: i32,
+  },
+  SendStopSignal {
+    name:             String,
+    signal:           String,
+    open_connections: i32,
+  },
+  KillAfterTimeout {
+    name: String,
+  },
+  GarbageCollect {
+    description: String,
+  },
+  AdoptProcess {
+    name:         String,
+    process_name: String,
+    pid:          u32,
+  },
+  TerminateOrphan {
+    name: String,
+    pid:  u32,
+  },
+  OutOfMemory {
+    name: String,
+  },
+  TriggerJob {
+    job_name: String,
+    trigger:  JobTrigger,
+  },
+  JobFinished {
+    name:        String,
+    job_name:    String,
+    exit_status: i32,
+  },
   WeightChange {
     service: String,
     port:    u16,
@@ -79,17 +269,57 @@ pub struct ResourceListEntry {
 pub enum ClientRequest {
   Ping,
   GetTarget,
-  SetTarget { target: String },
-  GetSecrets { names: Vec<String> },
-  SetSecret { name: String, value: String },
-  DeleteSecrets { names: Vec<String> },
+  SetTarget {
+    target: String,
+  },
+  GetSecrets {
+    names: Vec<String>,
+  },
+  SetSecret {
+    name:  String,
+    value: String,
+  },
+  DeleteSecrets {
+    names: Vec<String>,
+  },
   ListSecrets,
   Status,
-  GetLogs { name: String },
-  Restart { name: String },
-  DeleteResources { ids: Vec<String> },
+  GetLogs {
+    name:     String,
+    #[serde(default)]
+    hook:     bool,
+    #[serde(default)]
+    options:  LogOptions,
+    #[serde(default)]
+    versions: LogVersions,
+  },
+  Restart {
+    name: String,
+  },
+  RunJob {
+    name: String,
+  },
+  DeleteResources {
+    ids: Vec<String>,
+  },
   ListResources,
+  GarbageCollect {
+    dry_run: bool,
+  },
   ClearLaunchRateLimits,
+  /// Gets crash reports, optionally just for one process (by spec or version name).
+  GetCrashReports {
+    process: Option<String>,
+  },
+  /// Searches the output of every version of every process (or just of those named) for lines
+  /// matching a regex.
+  SearchLogs {
+    pattern:    String,
+    #[serde(default)]
+    processes:  Vec<String>,
+    /// Only search lines received within this many seconds.
+    since_secs: Option<u64>,
+  },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -114,6 +344,14 @@ pub enum ClientResponse {
     name:   String,
     output: String,
   },
+  CrashReports {
+    reports: Vec<CrashReport>,
+  },
+  LogMatches {
+    matches:   Vec<LogMatch>,
+    /// If there were more matches than we returned, in which case we return the newest.
+    truncated: bool,
+  },
   Resource {
     id:   String,
     data: Vec<u8>,
@@ -203,6 +441,11 @@ pub fn get_target_path() -> Result<PathBuf, Error> {
   Ok(launcho_dir.join("launcho-target.yaml"))
 }
 
+pub fn get_processes_path() -> Result<PathBuf, Error> {
+  let launcho_dir = get_launcho_directory()?;
+  Ok(launcho_dir.join("launcho-processes.json"))
+}
+
 pub fn get_extra_secrets_path() -> Result<PathBuf, Error> {
   let launcho_dir = get_launcho_directory()?;
   Ok(launcho_dir.join("launcho-extra-secrets.yaml"))
@@ -0,0 +1,301 @@
+use std::{
+  ffi::CString,
+  os::unix::ffi::OsStrExt,
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicI32, Ordering},
+};
+
+use anyhow::{bail, Context, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::config::IsolationSpec;
+
+/// Signals that get passed on to the sandboxed process, so that stop signals work as usual.
+static FORWARDED_SIGNALS: &[libc::c_int] = &[
+  libc::SIGTERM,
+  libc::SIGINT,
+  libc::SIGHUP,
+  libc::SIGQUIT,
+  libc::SIGUSR1,
+  libc::SIGUSR2,
+];
+/// Mounts under these are left alone when making everything read-only.
+static KERNEL_MOUNTS: &[&str] = &["/dev", "/proc", "/sys"];
+
+/// Everything the sandbox helper needs to know to set up the sandbox and run the command in it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SandboxConfig {
+  pub isolation: IsolationSpec,
+  pub cwd:       PathBuf,
+  /// Paths to bind into the sandbox writable, like the cwd and resources.
+  pub writable:  Vec<PathBuf>,
+  pub uid:       Option<u32>,
+  pub gid:       Option<u32>,
+  pub command:   Vec<String>,
+}
+
+impl SandboxConfig {
+  /// Makes a command that runs the sandbox helper, which is this same binary. The helper starts
+  /// out as root, and switches to the configured uid and gid once the sandbox is set up.
+  pub fn to_command(&self) -> Result<tokio::process::Command, Error> {
+    // Going through /proc/self/exe keeps working even if our binary has been replaced on disk.
+    let mut command = tokio::process::Command::new("/proc/self/exe");
+    command.args(["uncommon", "sandbox", &serde_json::to_string(self)?]);
+    Ok(command)
+  }
+}
+
+fn check(result: libc::c_int, what: &str) -> Result<(), Error> {
+  if result < 0 {
+    return Err(std::io::Error::last_os_error()).context(what.to_string());
+  }
+  Ok(())
+}
+
+fn c_path(path: &Path) -> Result<CString, Error> {
+  Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+fn mount(
+  source: Option<&str>,
+  target: &Path,
+  fstype: Option<&str>,
+  flags: libc::c_ulong,
+  data: Option<&str>,
+) -> Result<(), Error> {
+  let source = source.map(CString::new).transpose()?;
+  let fstype = fstype.map(CString::new).transpose()?;
+  let data = data.map(CString::new).transpose()?;
+  let as_ptr = |s: &Option<CString>| s.as_ref().map_or(std::ptr::null(), |s| s.as_ptr());
+  let result = unsafe {
+    libc::mount(
+      as_ptr(&source),
+      c_path(target)?.as_ptr(),
+      as_ptr(&fstype),
+      flags,
+      as_ptr(&data) as *const libc::c_void,
+    )
+  };
+  check(result, &format!("Failed to mount {:?} on {:?}", source, target))
+}
+
+/// Returns each mount point, along with the flags it's mounted with that need keeping when
+/// remounting it.
+fn list_mounts() -> Result<Vec<(PathBuf, libc::c_ulong)>, Error> {
+  let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+  let mut mounts = Vec::new();
+  for line in mountinfo.lines() {
+    let fields: Vec<&str> = line.split(' ').collect();
+    let (Some(mount_point), Some(options)) = (fields.get(4), fields.get(5)) else {
+      bail!("Bad line in mountinfo: {:?}", line);
+    };
+    // Spaces and such in paths are escaped in octal, like \040.
+    let mut path = Vec::new();
+    let mut bytes = mount_point.bytes();
+    while let Some(byte) = bytes.next() {
+      match byte {
+        b'\\' => {
+          let digits: Vec<u8> = bytes.by_ref().take(3).collect();
+          path.push(u8::from_str_radix(std::str::from_utf8(&digits)?, 8)?);
+        }
+        _ => path.push(byte),
+      }
+    }
+    let mut flags = 0;
+    for option in options.split(',') {
+      flags |= match option {
+        "nosuid" => libc::MS_NOSUID,
+        "nodev" => libc::MS_NODEV,
+        "noexec" => libc::MS_NOEXEC,
+        "noatime" => libc::MS_NOATIME,
+        "nodiratime" => libc::MS_NODIRATIME,
+        "relatime" => libc::MS_RELATIME,
+        _ => 0,
+      };
+    }
+    mounts.push((PathBuf::from(std::ffi::OsStr::from_bytes(&path)), flags));
+  }
+  Ok(mounts)
+}
+
+static FORWARD_TO: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward_signal(signal: libc::c_int) {
+  let pid = FORWARD_TO.load(Ordering::Relaxed);
+  if pid > 0 {
+    unsafe { libc::kill(pid, signal) };
+  }
+}
+
+/// Forwards signals to the given child, and reaps every child until it exits. Returns its exit
+/// code, where death by a signal is reported like a shell would, as 128 plus the signal number.
+fn forward_signals_and_wait(pid: libc::pid_t) -> i32 {
+  FORWARD_TO.store(pid, Ordering::Relaxed);
+  for signal in FORWARDED_SIGNALS {
+    unsafe {
+      let mut action: libc::sigaction = std::mem::zeroed();
+      action.sa_sigaction = forward_signal as *const () as usize;
+      action.sa_flags = libc::SA_RESTART;
+      libc::sigaction(*signal, &action, std::ptr::null_mut());
+    }
+  }
+  loop {
+    let mut status = 0;
+    let reaped = unsafe { libc::waitpid(-1, &mut status, 0) };
+    if reaped < 0 {
+      match std::io::Error::last_os_error().raw_os_error() {
+        Some(libc::EINTR) => continue,
+        _ => return 127,
+      }
+    }
+    if reaped != pid {
+      continue;
+    }
+    if libc::WIFEXITED(status) {
+      return libc::WEXITSTATUS(status);
+    }
+    if libc::WIFSIGNALED(status) {
+      return 128 + libc::WTERMSIG(status);
+    }
+  }
+}
+
+extern "C" fn parent_died(_signal: libc::c_int) {
+  unsafe { libc::_exit(128 + libc::SIGKILL) };
+}
+
+fn fork() -> Result<libc::pid_t, Error> {
+  let pid = unsafe { libc::fork() };
+  check(pid, "Failed to fork")?;
+  Ok(pid)
+}
+
+/// Sets up mounts inside of the new mount namespace.
+fn set_up_mounts(config: &SandboxConfig) -> Result<(), Error> {
+  // Hold on to everything we're going to bind, as mounting over /tmp might hide some of it.
+  let mut binds = Vec::new();
+  for path in std::iter::once(&config.cwd).chain(&config.writable) {
+    let fd = unsafe { libc::open(c_path(path)?.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    check(fd, &format!("Failed to open {:?}", path))?;
+    binds.push((path, fd, path.is_dir()));
+  }
+
+  // Note which mounts there are before we add our own, as those are the ones to make read-only.
+  let host_mounts = match config.isolation.read_only_root {
+    true => list_mounts()?,
+    false => Vec::new(),
+  };
+  if config.isolation.private_tmp {
+    let flags = libc::MS_NOSUID | libc::MS_NODEV;
+    mount(Some("tmpfs"), Path::new("/tmp"), Some("tmpfs"), flags, Some("mode=1777"))?;
+  }
+  // We're in a new PID namespace, so we need a /proc to match.
+  let flags = libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC;
+  mount(Some("proc"), Path::new("/proc"), Some("proc"), flags, None)?;
+
+  // Bind mounts copy the flags of their source, so these have to happen before making things
+  // read-only.
+  for (path, fd, is_dir) in &binds {
+    // If the path got hidden, then we need to make something to mount over.
+    if !path.exists() {
+      match is_dir {
+        true => std::fs::create_dir_all(path)?,
+        false => {
+          std::fs::create_dir_all(path.parent().unwrap_or(Path::new("/")))?;
+          std::fs::write(path, "")?;
+        }
+      }
+    }
+    let source = format!("/proc/self/fd/{}", fd);
+    mount(Some(&source), path, None, libc::MS_BIND | libc::MS_REC, None)?;
+    unsafe { libc::close(*fd) };
+  }
+
+  for (mount_point, flags) in host_mounts {
+    // Leave alone the kernel's mounts, and anything that is now hidden or covered by our own.
+    if KERNEL_MOUNTS.iter().any(|kernel_mount| mount_point.starts_with(kernel_mount))
+      || (config.isolation.private_tmp && mount_point.starts_with("/tmp"))
+      || binds.iter().any(|(path, _, _)| mount_point.starts_with(path))
+    {
+      continue;
+    }
+    let flags = flags | libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY;
+    mount(None, &mount_point, None, flags, None)?;
+  }
+  Ok(())
+}
+
+fn exec(config: &SandboxConfig) -> Result<(), Error> {
+  let command = config
+    .command
+    .iter()
+    .map(|arg| CString::new(arg.as_str()))
+    .collect::<Result<Vec<_>, _>>()?;
+  let mut argv: Vec<*const libc::c_char> = command.iter().map(|arg| arg.as_ptr()).collect();
+  argv.push(std::ptr::null());
+  unsafe { libc::execvp(argv[0], argv.as_ptr()) };
+  Err(std::io::Error::last_os_error())
+    .with_context(|| format!("Failed to run {:?}", config.command))
+}
+
+/// Runs as PID 1 of the new PID namespace. Once the sandbox is set up, we launch the command as
+/// our child, as PID 1 ignores signals it has no handler for, and stay around to forward signals
+/// and reap orphans. When we exit, the kernel kills everything else in the namespace.
+fn sandbox_init(config: &SandboxConfig) -> Result<i32, Error> {
+  set_up_mounts(config)?;
+  std::env::set_current_dir(&config.cwd)
+    .with_context(|| format!("Failed to change to {:?}", config.cwd))?;
+  if let Some(gid) = config.gid {
+    check(unsafe { libc::setgid(gid) }, "Failed to set gid")?;
+  }
+  if let Some(uid) = config.uid {
+    check(unsafe { libc::setgroups(0, std::ptr::null()) }, "Failed to drop groups")?;
+    check(unsafe { libc::setuid(uid) }, "Failed to set uid")?;
+  }
+  // Don't outlive the helper, which is what the launcho server keeps track of. As PID 1, even
+  // SIGKILL from the kernel is ignored, so we exit from a handler instead. This has to come after
+  // switching identity, which clears the parent death signal.
+  unsafe {
+    let mut action: libc::sigaction = std::mem::zeroed();
+    action.sa_sigaction = parent_died as *const () as usize;
+    libc::sigaction(libc::SIGRTMIN(), &action, std::ptr::null_mut());
+  }
+  let result = unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGRTMIN()) };
+  check(result, "Failed to set the parent death signal")?;
+  match fork()? {
+    0 => {
+      exec(config)?;
+      unreachable!()
+    }
+    pid => Ok(forward_signals_and_wait(pid)),
+  }
+}
+
+/// The entry point of the sandbox helper. This must run before any other threads are started, as
+/// unsharing a mount namespace requires being single threaded.
+pub fn sandbox_main(config: &str) -> Result<(), Error> {
+  let config: SandboxConfig = serde_json::from_str(config)?;
+  let namespaces = libc::CLONE_NEWNS | libc::CLONE_NEWPID | libc::CLONE_NEWIPC | libc::CLONE_NEWUTS;
+  check(unsafe { libc::unshare(namespaces) }, "Failed to unshare namespaces")?;
+  // Keep our mounts from propagating back out to the host.
+  mount(None, Path::new("/"), None, libc::MS_REC | libc::MS_PRIVATE, None)?;
+  if let Some(hostname) = &config.isolation.hostname {
+    let result =
+      unsafe { libc::sethostname(hostname.as_ptr() as *const libc::c_char, hostname.len()) };
+    check(result, "Failed to set hostname")?;
+  }
+  // Only our children go into the new PID namespace.
+  match fork()? {
+    0 => {
+      let code = match sandbox_init(&config) {
+        Ok(code) => code,
+        Err(e) => {
+          eprintln!("launcho sandbox: {:#}", e);
+          127
+        }
+      };
+      std::process::exit(code);
+    }
+    pid => std::process::exit(forward_signals_and_wait(pid)),
+  }
+}
@@ -1,51 +1,52 @@
 use std::{
   collections::{HashMap, HashSet, VecDeque},
-  path::PathBuf,
+  path::{Path, PathBuf},
   process::Stdio,
   sync::{atomic, Arc, Mutex},
 };
 
 use anyhow::{anyhow, bail, Context, Error};
+use serde::{Deserialize, Serialize};
 use tokio::{
   io::AsyncRead,
-  process::{ChildStderr, ChildStdout},
   sync::{Mutex as TokioMutex, MutexGuard as TokioMutexGuard},
 };
 use warp::Filter;
 
 use crate::{
+  cgroup,
   config::{
-    delete_extra_secrets, insert_and_save_secret, AuthConfig, LaunchoConfig, LaunchoTarget,
-    ProcessSpec, Secrets, ServiceSpec,
+    delete_extra_secrets, insert_and_save_secret, AuthConfig, HealthCheckKind, HealthCheckSpec,
+    HttpCheckSpec, JobSpec, LaunchoConfig, LaunchoTarget, LogsSpec, OrphanPolicy, ProcessSpec,
+    RestartPolicy, RetentionSpec, Secrets, ServiceSpec,
   },
-  get_auth_config, get_target, get_target_path, guarantee_launcho_directory, storage, ClientRequest,
-  ClientResponse, LogEvent, ProcessStatus,
+  cron, get_auth_config, get_processes_path, get_target, get_target_path,
+  guarantee_launcho_directory, storage, ClientRequest, ClientResponse, CrashReport, ExitReason,
+  JobTrigger, LifecycleHook, LogEvent, LogMatch, LogOptions, LogVersions, OutputStream,
+  ProcessStatus, StartFailure,
 };
-use crate::{ipvs, GetAuthConfigMode};
+use crate::{ipvs, sandbox::SandboxConfig, GetAuthConfigMode};
 
 static SERVICE_IP_PREFIX: &str = "127.0.0.";
 static HOUSEKEEPING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3);
-static CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
-
-// FIXME: These rate limits can only be evaluated in increments of the housekeeping interval.
-static START_RATE_LIMIT: &RateLimit = &RateLimit {
-  prefix:           "start",
-  duration:         std::time::Duration::from_secs(2),
-  backoff_duration: std::time::Duration::from_secs(5),
-  max_attempts:     2,
-};
-static HEALTH_CHECK_RATE_LIMIT: &RateLimit = &RateLimit {
-  prefix:           "health_check",
-  duration:         std::time::Duration::from_secs(60),
-  backoff_duration: std::time::Duration::from_secs(60),
-  max_attempts:     1,
-};
-static LAUNCH_RATE_LIMIT: &RateLimit = &RateLimit {
-  prefix:           "launch",
-  duration:         std::time::Duration::from_secs(30),
-  backoff_duration: std::time::Duration::from_secs(1200),
-  max_attempts:     3,
-};
+/// How often the prober looks for health checks that are due.
+static PROBE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+/// How often we look for old log files to delete.
+static LOG_PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+static ORPHAN_KILL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// How far back status counts restarts.
+static RESTART_WINDOW: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+static RECENT_EXITS_SHOWN: usize = 5;
+
+/// Formats a duration to the largest whole unit, like "14m", rounding up.
+fn format_rough_duration(duration: std::time::Duration) -> String {
+  let secs = duration.as_secs() + (duration.subsec_nanos() > 0) as u64;
+  match secs {
+    0..=59 => format!("{}s", secs),
+    60..=3599 => format!("{}m", secs.div_ceil(60)),
+    _ => format!("{}h", secs.div_ceil(3600)),
+  }
+}
 
 fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
   use subtle::ConstantTimeEq;
@@ -117,184 +118,1989 @@ fn test_port(port: u16) -> Result<bool, Error> {
       Err(anyhow!("Failed to bind socket: {}", e))
     }
   }
-}
+}
+
+fn parse_signal(name: &str) -> Result<i32, Error> {
+  Ok(match name.trim_start_matches("SIG") {
+    "HUP" => libc::SIGHUP,
+    "INT" => libc::SIGINT,
+    "QUIT" => libc::SIGQUIT,
+    "KILL" => libc::SIGKILL,
+    "USR1" => libc::SIGUSR1,
+    "USR2" => libc::SIGUSR2,
+    "TERM" => libc::SIGTERM,
+    "WINCH" => libc::SIGWINCH,
+    _ => bail!("Unknown signal {:?}", name),
+  })
+}
+
+const LOG_MAX_SIZE: usize = 1000;
+static LOG_EVENTS: Mutex<VecDeque<LogEvent>> = Mutex::new(VecDeque::new());
+
+pub fn log_event(event: LogEvent) {
+  eprintln!("\x1b[93m[Event]\x1b[0m {:?}", event);
+  let mut events = LOG_EVENTS.lock().unwrap();
+  events.push_back(event);
+  while events.len() > LOG_MAX_SIZE {
+    events.pop_front();
+  }
+}
+
+pub fn get_entire_log() -> Vec<LogEvent> {
+  LOG_EVENTS.lock().unwrap().iter().cloned().collect()
+}
+
+const MAX_CRASH_REPORTS: usize = 100;
+/// How much of a crashed process's output to keep in its crash report.
+const CRASH_REPORT_OUTPUT_BYTES: usize = 8 * 1024;
+/// How long to wait for the rest of a crashed process's output. Anything it forked off may hold
+/// its stdout open, so we can't wait for EOF indefinitely.
+const OUTPUT_EOF_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(200);
+static CRASH_REPORTS: Mutex<VecDeque<CrashReport>> = Mutex::new(VecDeque::new());
+
+fn record_crash(report: CrashReport) {
+  let mut reports = CRASH_REPORTS.lock().unwrap();
+  reports.push_back(report);
+  while reports.len() > MAX_CRASH_REPORTS {
+    reports.pop_front();
+  }
+}
+
+/// Gets crash reports, oldest first, for either all processes or just the given process (which
+/// may be a spec name or a version name).
+pub fn get_crash_reports(process: Option<&str>) -> Vec<CrashReport> {
+  let reports = CRASH_REPORTS.lock().unwrap();
+  reports
+    .iter()
+    .filter(|report| process.is_none_or(|p| report.process_name == p || report.name == p))
+    .cloned()
+    .collect()
+}
+
+/// Sets up a command to run the way a process's main command does: in its cwd, and with its env
+/// and service ports.
+fn configure_command(
+  command: &mut tokio::process::Command,
+  process_spec: &ProcessSpec,
+  cwd: &Path,
+  port_allocations: &HashMap<String, u16>,
+) -> Result<(), Error> {
+  command.current_dir(cwd);
+  for (key, value) in &process_spec.env {
+    command.env(key, value);
+  }
+  for (service_name, port) in port_allocations {
+    command.env(format!("SERVICE_PORT_{}", service_name.to_uppercase()), port.to_string());
+  }
+  Ok(())
+}
+
+/// Makes a command run in the process's cgroup, as its user.
+fn set_identity(
+  command: &mut tokio::process::Command,
+  process_spec: &ProcessSpec,
+  cgroup: Option<&Path>,
+) -> Result<(), Error> {
+  let uid = process_spec.uid.as_ref().map(|uid| uid.to_uid()).transpose()?;
+  let gid = process_spec.gid.as_ref().map(|gid| gid.to_gid()).transpose()?;
+  match cgroup {
+    // Joining the cgroup needs root, so the identity switch has to happen after that.
+    Some(cgroup) => cgroup::enter_on_spawn(command, cgroup, uid, gid)?,
+    None => {
+      if let Some(uid) = uid {
+        command.uid(uid);
+      }
+      if let Some(gid) = gid {
+        command.gid(gid);
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Gives a file we created to the process's user, so that its hooks can modify it.
+fn chown_to_process(process_spec: &ProcessSpec, path: &Path) -> Result<(), Error> {
+  let uid = process_spec.uid.as_ref().map(|uid| uid.to_uid()).transpose()?;
+  let gid = process_spec.gid.as_ref().map(|gid| gid.to_gid()).transpose()?;
+  if uid.is_some() || gid.is_some() {
+    std::os::unix::fs::chown(path, uid, gid)
+      .with_context(|| format!("Failed to chown {:?}", path))?;
+  }
+  Ok(())
+}
+
+/// The kinds of health probes a process can have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Probe {
+  /// Holds up sending a process traffic until it passes, and takes it out of rotation while
+  /// failing.
+  Readiness,
+  /// Gets a running process replaced when failing.
+  Liveness,
+}
+
+static PROBES: &[Probe] = &[Probe::Readiness, Probe::Liveness];
+
+impl Probe {
+  /// Finds the check to run for this probe. A plain health check acts as the readiness probe.
+  fn check(self, spec: &ProcessSpec) -> Option<&HealthCheckSpec> {
+    match self {
+      Probe::Readiness => spec.readiness.as_ref().or(spec.health.as_ref()),
+      Probe::Liveness => spec.liveness.as_ref(),
+    }
+  }
+
+  fn runs_while(self, status: ProcessStatus) -> bool {
+    match self {
+      Probe::Readiness => matches!(status, ProcessStatus::Starting | ProcessStatus::Running),
+      Probe::Liveness => status == ProcessStatus::Running,
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+struct ProbeState {
+  last_check: Option<std::time::Instant>,
+  /// How many checks in a row have passed, or failed.
+  successes:  u32,
+  failures:   u32,
+}
+
+impl ProbeState {
+  /// Checks if it's time for another check, going by the initial delay and interval.
+  fn is_due(&self, probe: Probe, check: &HealthCheckSpec, started: std::time::Instant) -> bool {
+    // Looking for READY=1 costs nothing, so there's no reason to make the process wait.
+    if probe == Probe::Readiness && matches!(check.kind, HealthCheckKind::Notify {}) {
+      return true;
+    }
+    match self.last_check {
+      Some(last_check) => last_check.elapsed() >= check.interval.0,
+      None => started.elapsed() >= check.initial_delay.0,
+    }
+  }
+
+  fn record(&mut self, healthy: bool) {
+    match healthy {
+      true => {
+        self.successes += 1;
+        self.failures = 0;
+      }
+      false => {
+        self.failures += 1;
+        self.successes = 0;
+      }
+    }
+  }
+}
+
+/// Everything needed to health check a process, so that checks can run without holding the
+/// global lock.
+struct HealthProbe {
+  name:             String,
+  probe:            Probe,
+  check:            HealthCheckSpec,
+  spec:             ProcessSpec,
+  cwd:              PathBuf,
+  port_allocations: HashMap<String, u16>,
+  notify:           Option<Arc<Mutex<NotifyState>>>,
+}
+
+impl HealthProbe {
+  async fn run(&self) -> Result<bool, Error> {
+    let health_check_spec = &self.check;
+    let service_port = |service: &str| {
+      self
+        .port_allocations
+        .get(service)
+        .copied()
+        .ok_or_else(|| anyhow!("BUG: No port allocated for service {}", service))
+    };
+    let timeout = health_check_spec.timeout.0;
+    match &health_check_spec.kind {
+      HealthCheckKind::Tcp { service } => {
+        let port = service_port(service)?;
+        let connect = tokio::net::TcpStream::connect(("localhost", port));
+        Ok(matches!(tokio::time::timeout(timeout, connect).await, Ok(Ok(_))))
+      }
+      HealthCheckKind::Exec { command: argv } => {
+        let mut command = tokio::process::Command::new(&argv[0]);
+        command
+          .args(&argv[1..])
+          .stdin(Stdio::null())
+          .stdout(Stdio::null())
+          .stderr(Stdio::null())
+          .kill_on_drop(true);
+        configure_command(&mut command, &self.spec, &self.cwd, &self.port_allocations)?;
+        set_identity(&mut command, &self.spec, None)?;
+        let mut child =
+          command.spawn().with_context(|| format!("Failed to run health check {:?}", argv))?;
+        Ok(match tokio::time::timeout(timeout, child.wait()).await {
+          Ok(status) => status?.success(),
+          // The child gets killed on drop.
+          Err(_) => false,
+        })
+      }
+      HealthCheckKind::Http(http) =>
+        http_health_check("http", http, service_port(&http.service)?, timeout).await,
+      HealthCheckKind::Https(http) =>
+        http_health_check("https", http, service_port(&http.service)?, timeout).await,
+      HealthCheckKind::Notify {} => {
+        let notify = self.notify.as_ref().ok_or_else(|| anyhow!("No notify socket"))?;
+        let notify = notify.lock().unwrap();
+        Ok(match self.probe {
+          Probe::Readiness => notify.ready,
+          Probe::Liveness => notify.last_watchdog.elapsed() <= health_check_spec.interval.0,
+        })
+      }
+    }
+  }
+}
+
+/// What a process has told us over its notify socket.
+struct NotifyState {
+  ready:         bool,
+  /// The last STATUS= text, which describes what the process is up to.
+  status:        Option<String>,
+  /// When we last got WATCHDOG=1, or when the socket was made, if we haven't yet.
+  last_watchdog: std::time::Instant,
+}
+
+impl NotifyState {
+  fn handle_message(&mut self, message: &str) {
+    for line in message.lines() {
+      match line.split_once('=') {
+        Some(("READY", "1")) => self.ready = true,
+        Some(("STATUS", status)) => self.status = Some(status.to_string()),
+        Some(("WATCHDOG", "1")) => self.last_watchdog = std::time::Instant::now(),
+        // Other messages, like MAINPID= or STOPPING=1, don't mean anything to us.
+        _ => {}
+      }
+    }
+  }
+}
+
+/// Where notify sockets go. Processes may run as other users, who can't get into a root server's
+/// home directory, so a root server keeps them under /run instead.
+fn get_notify_directory() -> Result<PathBuf, Error> {
+  match unsafe { libc::geteuid() } {
+    0 => Ok(PathBuf::from("/run/launcho-notify")),
+    _ => Ok(crate::get_launcho_directory()?.join("notify")),
+  }
+}
+
+/// A datagram socket a process can tell us about itself over, following systemd's sd_notify
+/// protocol. Each socket is in its own directory that only the process's user can get into, so
+/// that nobody else can claim to be the process.
+struct NotifySocket {
+  /// The address to give the process in $NOTIFY_SOCKET.
+  address:  String,
+  dir:      PathBuf,
+  state:    Arc<Mutex<NotifyState>>,
+  listener: tokio::task::JoinHandle<()>,
+}
+
+impl NotifySocket {
+  /// Makes the socket for the given version. It's named after the version, so that an adopted
+  /// process can keep talking to us after a launcho server restart.
+  fn bind(name: &str, spec: &ProcessSpec, ready: bool) -> Result<Self, Error> {
+    use std::os::unix::fs::PermissionsExt;
+    let notify_dir = get_notify_directory()?;
+    std::fs::create_dir_all(&notify_dir)
+      .with_context(|| format!("Failed to create {:?}", notify_dir))?;
+    // Others can get into the directories of the processes they run as, but not list them.
+    std::fs::set_permissions(&notify_dir, std::fs::Permissions::from_mode(0o711))?;
+    let dir = notify_dir.join(name);
+    crate::already_exists_ok(std::fs::create_dir(&dir))
+      .with_context(|| format!("Failed to create {:?}", dir))?;
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    chown_to_process(spec, &dir)?;
+    let path = dir.join("notify");
+    // An adopted process's socket is left over from the last server.
+    match std::fs::remove_file(&path) {
+      Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+      _ => {}
+    }
+    let socket = std::os::unix::net::UnixDatagram::bind(&path)
+      .with_context(|| format!("Failed to bind notify socket {:?}", path))?;
+    chown_to_process(spec, &path)?;
+    socket.set_nonblocking(true)?;
+    let socket = tokio::net::UnixDatagram::from_std(socket)?;
+    let state = Arc::new(Mutex::new(NotifyState {
+      ready,
+      status: None,
+      last_watchdog: std::time::Instant::now(),
+    }));
+    let listener = tokio::spawn({
+      let state = state.clone();
+      async move {
+        let mut buf = vec![0; 4096];
+        while let Ok(n) = socket.recv(&mut buf).await {
+          state.lock().unwrap().handle_message(&String::from_utf8_lossy(&buf[..n]));
+        }
+      }
+    });
+    Ok(Self {
+      address: path.to_string_lossy().to_string(),
+      dir,
+      state,
+      listener,
+    })
+  }
+
+  /// Removes the socket left behind by a process we aren't adopting.
+  fn remove_leftovers(name: &str) {
+    if let Ok(notify_dir) = get_notify_directory() {
+      std::fs::remove_dir_all(notify_dir.join(name)).ok();
+    }
+  }
+
+  fn status(&self) -> Option<String> {
+    self.state.lock().unwrap().status.clone()
+  }
+}
+
+impl Drop for NotifySocket {
+  fn drop(&mut self) {
+    self.listener.abort();
+    std::fs::remove_dir_all(&self.dir).ok();
+  }
+}
+
+/// Checks if a process uses the notify protocol, and so needs a notify socket.
+fn uses_notify(spec: &ProcessSpec) -> bool {
+  [&spec.readiness, &spec.liveness]
+    .into_iter()
+    .flatten()
+    .any(|check| matches!(check.kind, HealthCheckKind::Notify {}))
+}
+
+async fn http_health_check(
+  scheme: &str,
+  spec: &HttpCheckSpec,
+  port: u16,
+  timeout: std::time::Duration,
+) -> Result<bool, Error> {
+  let maybe_slash = if spec.path.starts_with("/") { "" } else { "/" };
+  // We connect to localhost, so there's no certificate that could be expected to verify.
+  let client = reqwest::Client::builder()
+    .timeout(timeout)
+    .danger_accept_invalid_certs(scheme == "https")
+    .build()?;
+  let method = reqwest::Method::from_bytes(spec.method.as_bytes())?;
+  let url = format!("{}://localhost:{}{}{}", scheme, port, maybe_slash, spec.path);
+  let mut request = client.request(method, url);
+  for (name, value) in &spec.headers {
+    request = request.header(name, value);
+  }
+  if let Some(host) = &spec.host {
+    request = request.header(reqwest::header::HOST, host);
+  }
+  let response = match request.send().await {
+    Ok(response) => response,
+    Err(e) if e.is_connect() || e.is_timeout() => return Ok(false),
+    Err(e) => return Err(e.into()),
+  };
+  let status = response.status();
+  let status_ok = match spec.expected_status.is_empty() {
+    true => status.is_success(),
+    false => spec.expected_status.contains(&status.as_u16()),
+  };
+  if !status_ok {
+    return Ok(false);
+  }
+  let Some(body_contains) = &spec.body_contains else {
+    return Ok(true);
+  };
+  Ok(match response.text().await {
+    Ok(body) => body.contains(body_contains.as_str()),
+    Err(e) if e.is_timeout() => false,
+    Err(e) => return Err(e.into()),
+  })
+}
+
+/// Copies a process's resources into its working directory, and runs its before steps.
+/// This runs off of the global lock, as unpacking a big bundle can take a while.
+async fn prepare_process(
+  process_spec: &ProcessSpec,
+  cwd: &Path,
+  cgroup: Option<&Path>,
+  port_allocations: &HashMap<String, u16>,
+  hook_steps: &[Arc<HookStep>],
+) -> Result<(), Error> {
+  // Unpack requested resources.
+  for resource_request in &process_spec.resources {
+    let id = resource_request.id.clone();
+    let target = cwd.join(&resource_request.file);
+    let process_spec = process_spec.clone();
+    tokio::task::spawn_blocking(move || {
+      storage::copy_resource(&id, &target)?;
+      chown_to_process(&process_spec, &target)
+    })
+    .await??;
+  }
+  // Perform the before steps, in order.
+  for (i, step) in hook_steps.iter().enumerate() {
+    let status = match run_hook_step(step, process_spec, cwd, cgroup, port_allocations).await {
+      Ok(status) => status,
+      Err(e) => {
+        step.finish(format!("failed to run: {:#}", e));
+        bail!("Failed to run before step {}: {:#}", i + 1, e);
+      }
+    };
+    step.finish(status.to_string());
+    if !status.success() {
+      bail!("Before step {} failed with {}", i + 1, status);
+    }
+  }
+  Ok(())
+}
+
+/// Runs a hook's shell command the way the process's main command runs, spooling its output into
+/// the step. This doesn't set the step's result.
+async fn run_hook_step(
+  step: &HookStep,
+  process_spec: &ProcessSpec,
+  cwd: &Path,
+  cgroup: Option<&Path>,
+  port_allocations: &HashMap<String, u16>,
+) -> Result<std::process::ExitStatus, Error> {
+  step.mark_in_process_output(format!("running {}", step.label));
+  let mut command = tokio::process::Command::new("sh");
+  command.arg("-c").arg(&step.command);
+  configure_command(&mut command, process_spec, cwd, port_allocations)?;
+  set_identity(&mut command, process_spec, cgroup)?;
+  command
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    // If we time out, then dropping the child kills it.
+    .kill_on_drop(true);
+  let mut child = command.spawn()?;
+  step.output.attach(child.stdout.take().unwrap(), child.stderr.take().unwrap());
+  let status = child.wait().await?;
+  // Let the output catch up, so that it comes before the step's end marker.
+  step.output.wait_for_eof(OUTPUT_EOF_TIMEOUT).await;
+  Ok(status)
+}
+
+/// How much of each spool's output to keep in memory. With log files, the rest is only on disk.
+const MEMORY_TAIL_BYTES: usize = 64 * 1024;
+/// How many lines of output a follower may fall behind by before it misses some.
+const FOLLOW_BUFFERED_LINES: usize = 1024;
+/// Longer lines get broken up.
+const MAX_LINE_BYTES: usize = 16 * 1024;
+/// How many lines a log search returns at most.
+const MAX_SEARCH_MATCHES: usize = 1000;
+/// How often followers check for a newer version to move on to.
+static FOLLOW_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+fn get_logs_directory() -> Result<PathBuf, Error> {
+  Ok(crate::get_launcho_directory()?.join("logs"))
+}
+
+/// What a log directory is for, kept in it as `version.json`, so that a version's logs can still be
+/// found by its process's name once we no longer have its entry.
+#[derive(Serialize, Deserialize)]
+struct LogDirInfo {
+  process_name: String,
+  replica:      usize,
+}
+
+/// The log files of one version, as `output.log`, then `output.log.1` and so on for older output.
+/// Each line of output is written as a record (see `LogLine::to_record`).
+struct LogFiles {
+  dir:           PathBuf,
+  file:          std::fs::File,
+  size:          u64,
+  max_file_size: u64,
+  max_files:     usize,
+}
+
+impl LogFiles {
+  /// Opens the log files for the given version, appending to any that already exist.
+  fn open(name: &str, info: &LogDirInfo, logs: &LogsSpec) -> Result<Self, Error> {
+    let dir = get_logs_directory()?.join(name);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    std::fs::write(dir.join("version.json"), serde_json::to_string(info)?)?;
+    let file = Self::open_current(&dir)?;
+    Ok(Self {
+      size: file.metadata()?.len(),
+      dir,
+      file,
+      max_file_size: logs.max_file_size.0,
+      max_files: logs.max_files.max(1),
+    })
+  }
+
+  fn open_current(dir: &Path) -> Result<std::fs::File, Error> {
+    let path = dir.join("output.log");
+    std::fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&path)
+      .with_context(|| format!("Failed to open {:?}", path))
+  }
+
+  fn path(&self, index: usize) -> PathBuf {
+    match index {
+      0 => self.dir.join("output.log"),
+      _ => self.dir.join(format!("output.log.{}", index)),
+    }
+  }
+
+  fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+    use std::io::Write;
+    if self.size > 0 && self.size + data.len() as u64 > self.max_file_size {
+      self.rotate()?;
+    }
+    self.file.write_all(data)?;
+    self.size += data.len() as u64;
+    Ok(())
+  }
+
+  /// Shifts every file along by one, dropping the oldest, and starts a fresh current file.
+  fn rotate(&mut self) -> Result<(), Error> {
+    std::fs::remove_file(self.path(self.max_files - 1)).ok();
+    for index in (0..self.max_files - 1).rev() {
+      std::fs::rename(self.path(index), self.path(index + 1)).ok();
+    }
+    self.file = Self::open_current(&self.dir)?;
+    self.size = 0;
+    Ok(())
+  }
+}
+
+/// Reads back all of the output in a version's log directory, oldest first.
+fn read_log_dir(dir: &Path) -> Result<Vec<LogLine>, Error> {
+  let mut lines = Vec::new();
+  scan_log_dir(dir, |line| lines.push(line))?;
+  Ok(lines)
+}
+
+/// Goes through the output in a version's log directory, oldest first, a line at a time, so that
+/// it never has to all be in memory at once.
+fn scan_log_dir(dir: &Path, mut f: impl FnMut(LogLine)) -> Result<(), Error> {
+  use std::io::BufRead;
+  let mut paths = Vec::new();
+  for file in std::fs::read_dir(dir)? {
+    let file = file?;
+    let index = match file.file_name().to_str() {
+      Some("output.log") => 0,
+      Some(file_name) => match file_name.strip_prefix("output.log.").map(str::parse::<usize>) {
+        Some(Ok(index)) => index,
+        _ => continue,
+      },
+      None => continue,
+    };
+    paths.push((index, file.path()));
+  }
+  paths.sort_by_key(|(index, _)| std::cmp::Reverse(*index));
+  for (_, path) in paths {
+    let file = match std::fs::File::open(&path) {
+      Ok(file) => file,
+      // It was rotated away since we listed it.
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+      Err(e) => return Err(e.into()),
+    };
+    let mut reader = std::io::BufReader::new(file);
+    let mut record = Vec::new();
+    while reader.read_until(b'\n', &mut record)? > 0 {
+      let text = String::from_utf8_lossy(&record);
+      f(LogLine::from_record(text.strip_suffix('\n').unwrap_or(&text)));
+      record.clear();
+    }
+  }
+  Ok(())
+}
+
+/// Like `read_log_dir`, but warns about failures instead.
+fn try_read_log_dir(dir: &Path) -> Option<Vec<LogLine>> {
+  match read_log_dir(dir) {
+    Ok(lines) => Some(lines),
+    Err(e) => {
+      log_event(LogEvent::Warning {
+        msg: format!("Failed to read {:?}: {:#}", dir, e),
+      });
+      None
+    }
+  }
+}
+
+/// Lists log directories off the runtime, warning (and giving none) if that fails.
+async fn find_log_dirs() -> Result<Vec<LogDir>, Error> {
+  Ok(tokio::task::spawn_blocking(list_log_dirs).await?.unwrap_or_else(|e| {
+    log_event(LogEvent::Warning {
+      msg: format!("Failed to list log directories: {:#}", e),
+    });
+    Vec::new()
+  }))
+}
+
+/// A version's log directory, which may outlive the version's entry.
+struct LogDir {
+  name: String,
+  path: PathBuf,
+  /// Missing for directories from before we recorded it.
+  info: Option<LogDirInfo>,
+}
+
+/// Lists every version's log directory, oldest output first.
+fn list_log_dirs() -> Result<Vec<LogDir>, Error> {
+  let dirs = match std::fs::read_dir(get_logs_directory()?) {
+    Ok(dirs) => dirs,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(e) => return Err(e.into()),
+  };
+  let mut log_dirs = Vec::new();
+  for dir in dirs {
+    let dir = dir?;
+    let path = dir.path();
+    let info = std::fs::read(path.join("version.json"))
+      .ok()
+      .and_then(|data| serde_json::from_slice(&data).ok());
+    let modified = std::fs::metadata(path.join("output.log")).and_then(|m| m.modified()).ok();
+    log_dirs.push((modified, LogDir {
+      name: dir.file_name().to_string_lossy().to_string(),
+      path,
+      info,
+    }));
+  }
+  log_dirs.sort_by_key(|(modified, _)| *modified);
+  Ok(log_dirs.into_iter().map(|(_, log_dir)| log_dir).collect())
+}
+
+/// Deletes log files that haven't been written to within the max age. The files being written by
+/// live versions are kept regardless, along with their directories.
+fn prune_logs(logs: &LogsSpec, live_names: &HashSet<String>) -> Result<(), Error> {
+  let logs_dir = get_logs_directory()?;
+  let dirs = match std::fs::read_dir(&logs_dir) {
+    Ok(dirs) => dirs,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+    Err(e) => return Err(e.into()),
+  };
+  for dir in dirs {
+    let dir = dir?;
+    let name = dir.file_name();
+    let is_live = live_names.contains(name.to_string_lossy().as_ref());
+    let mut remaining = 0;
+    for file in std::fs::read_dir(dir.path())? {
+      let file = file?;
+      // This says what the logs are for, so it goes along with the last of them.
+      if file.file_name() == "version.json" {
+        continue;
+      }
+      let age = file.metadata()?.modified()?.elapsed().unwrap_or_default();
+      // A live version is still writing to its current log file and FIFOs.
+      let in_use = is_live
+        && matches!(file.file_name().to_str(), Some("output.log" | "stdout.fifo" | "stderr.fifo"));
+      if age < logs.max_age.0 || in_use {
+        remaining += 1;
+        continue;
+      }
+      std::fs::remove_file(file.path())?;
+    }
+    if remaining == 0 && !is_live {
+      std::fs::remove_dir_all(dir.path())?;
+    }
+  }
+  Ok(())
+}
+
+/// FIFOs in a version's log directory, which its process writes its stdout and stderr to. Unlike
+/// pipes, these outlive the launcho server, so that a restarted server can carry on reading an
+/// adopted process's output. The process holds its ends open for reading as well as writing, so it
+/// never gets SIGPIPE while there's no server reading, though it blocks once a FIFO fills up.
+struct OutputFifos {
+  stdout: PathBuf,
+  stderr: PathBuf,
+}
+
+impl OutputFifos {
+  fn new(name: &str) -> Result<Self, Error> {
+    let dir = get_logs_directory()?.join(name);
+    Ok(Self {
+      stdout: dir.join("stdout.fifo"),
+      stderr: dir.join("stderr.fifo"),
+    })
+  }
+
+  fn create(&self) -> Result<(), Error> {
+    use std::os::unix::ffi::OsStrExt;
+    for path in [&self.stdout, &self.stderr] {
+      if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+      }
+      let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+      if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(std::io::Error::last_os_error())
+          .with_context(|| format!("Failed to create {:?}", path));
+      }
+    }
+    Ok(())
+  }
+
+  /// Opens the ends for the process, as its stdout and stderr.
+  fn open_for_process(&self) -> Result<(std::fs::File, std::fs::File), Error> {
+    let open = |path: &Path| {
+      std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {:?}", path))
+    };
+    Ok((open(&self.stdout)?, open(&self.stderr)?))
+  }
+
+  /// Opens the ends for us to read from. These read as ended straight away if nothing has the
+  /// FIFOs open for writing.
+  fn open_for_reading(
+    &self,
+  ) -> Result<(tokio::net::unix::pipe::Receiver, tokio::net::unix::pipe::Receiver), Error> {
+    let open = |path: &Path| {
+      tokio::net::unix::pipe::OpenOptions::new()
+        .open_receiver(path)
+        .with_context(|| format!("Failed to open {:?}", path))
+    };
+    Ok((open(&self.stdout)?, open(&self.stderr)?))
+  }
+
+  fn remove(&self) {
+    for path in [&self.stdout, &self.stderr] {
+      std::fs::remove_file(path).ok();
+    }
+  }
+}
+
+/// One line of output, as received.
+#[derive(Debug, Clone)]
+struct LogLine {
+  /// When we received it, in milliseconds since the epoch.
+  time_ms: u64,
+  stream:  OutputStream,
+  text:    String,
+}
+
+impl LogLine {
+  fn new(stream: OutputStream, text: &[u8]) -> Self {
+    let time_ms =
+      std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis()
+        as u64;
+    Self {
+      time_ms,
+      stream,
+      text: String::from_utf8_lossy(text).to_string(),
+    }
+  }
+
+  /// Lines are stored in log files as "TIME_MS out|err TEXT".
+  fn to_record(&self) -> String {
+    let stream = match self.stream {
+      OutputStream::Stdout => "out",
+      OutputStream::Stderr => "err",
+    };
+    format!("{} {} {}\n", self.time_ms, stream, self.text)
+  }
+
+  fn from_record(record: &str) -> Self {
+    let mut parts = record.splitn(3, ' ');
+    let time_ms = parts.next().and_then(|time_ms| time_ms.parse().ok());
+    let stream = match parts.next() {
+      Some("out") => Some(OutputStream::Stdout),
+      Some("err") => Some(OutputStream::Stderr),
+      _ => None,
+    };
+    match (time_ms, stream) {
+      (Some(time_ms), Some(stream)) => Self {
+        time_ms,
+        stream,
+        text: parts.next().unwrap_or_default().to_string(),
+      },
+      // Log files from before lines were tagged just have the raw output.
+      _ => Self {
+        time_ms: 0,
+        stream:  OutputStream::Stdout,
+        text:    record.to_string(),
+      },
+    }
+  }
+
+  fn matches(&self, options: &LogOptions, now_ms: u64) -> bool {
+    let since_ms = options
+      .since_secs
+      .map(|since_secs| now_ms.saturating_sub(since_secs.saturating_mul(1000)));
+    !(options.stderr_only && self.stream != OutputStream::Stderr)
+      && since_ms.is_none_or(|since_ms| self.time_ms >= since_ms)
+  }
+
+  fn format(&self, options: &LogOptions) -> String {
+    match options.timestamps {
+      true => format!("{} {}\n", format_timestamp(self.time_ms), self.text),
+      false => format!("{}\n", self.text),
+    }
+  }
+}
+
+/// Formats a time like "2024-01-02T03:04:05.678Z".
+pub fn format_timestamp(time_ms: u64) -> String {
+  let time = (time_ms / 1000) as libc::time_t;
+  let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+  unsafe { libc::gmtime_r(&time, &mut tm) };
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+    tm.tm_year + 1900,
+    tm.tm_mon + 1,
+    tm.tm_mday,
+    tm.tm_hour,
+    tm.tm_min,
+    tm.tm_sec,
+    time_ms % 1000
+  )
+}
+
+/// Applies the options to some lines, oldest first.
+fn format_lines<'a>(lines: impl Iterator<Item = &'a LogLine>, options: &LogOptions) -> String {
+  let now_ms = LogLine::new(OutputStream::Stdout, b"").time_ms;
+  let lines: Vec<&LogLine> = lines.filter(|line| line.matches(options, now_ms)).collect();
+  let start = match options.tail {
+    Some(tail) => lines.len().saturating_sub(tail),
+    None => 0,
+  };
+  lines[start..].iter().map(|line| line.format(options)).collect()
+}
+
+/// A version we can get the output of, whether or not we still have its entry.
+struct LogSource {
+  name:    String,
+  replica: usize,
+  output:  OutputSource,
+}
+
+enum OutputSource {
+  Spool(Arc<SpooledOutput>),
+  /// All that's left of a version whose entry is gone.
+  LogDir(PathBuf),
+}
+
+impl LogSource {
+  fn from_entry(entry: &RunningProcessEntry) -> Self {
+    Self {
+      name:    entry.name.clone(),
+      replica: entry.replica,
+      output:  OutputSource::Spool(entry.output.clone()),
+    }
+  }
+
+  fn from_log_dir(log_dir: &LogDir) -> Self {
+    Self {
+      name:    log_dir.name.clone(),
+      replica: log_dir.info.as_ref().map_or(0, |info| info.replica),
+      output:  OutputSource::LogDir(log_dir.path.clone()),
+    }
+  }
+}
+
+impl OutputSource {
+  /// Gets every line we have, oldest first. This may read through log files.
+  fn lines(&self) -> Vec<LogLine> {
+    match self {
+      OutputSource::Spool(output) => output.lines(),
+      OutputSource::LogDir(dir) => try_read_log_dir(dir).unwrap_or_default(),
+    }
+  }
+
+  /// Like `lines`, but goes through them one at a time instead of reading them all in.
+  fn for_each_line(&self, mut f: impl FnMut(LogLine)) {
+    let dir = match self {
+      OutputSource::Spool(output) => match output.log_dir() {
+        Some(dir) => dir,
+        None => return output.lines().into_iter().for_each(f),
+      },
+      OutputSource::LogDir(dir) => dir.clone(),
+    };
+    if let Err(e) = scan_log_dir(&dir, &mut f) {
+      log_event(LogEvent::Warning {
+        msg: format!("Failed to read {:?}: {:#}", dir, e),
+      });
+    }
+  }
+}
+
+/// Combines the output of several versions of a process in the order it came in, marking each
+/// line with the version it's from.
+fn combine_outputs(sources: &[LogSource], options: &LogOptions) -> String {
+  let mut lines = Vec::new();
+  for source in sources {
+    lines.extend(source.output.lines().into_iter().map(|line| LogLine {
+      text: format!("[{}] {}", source.name, line.text),
+      ..line
+    }));
+  }
+  // The sort is stable, so untimed lines from old log files stay in order.
+  lines.sort_by_key(|line| line.time_ms);
+  format_lines(lines.iter(), options)
+}
+
+/// A search match, ordered by when it came in, and then by when we found it.
+struct FoundMatch {
+  order: (u64, usize),
+  line:  LogMatch,
+}
+
+impl PartialEq for FoundMatch {
+  fn eq(&self, other: &Self) -> bool {
+    self.order == other.order
+  }
+}
+
+impl Eq for FoundMatch {}
+
+impl PartialOrd for FoundMatch {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for FoundMatch {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    self.order.cmp(&other.order)
+  }
+}
+
+/// Searches outputs, given as (process name, version name, output), for lines matching a pattern.
+/// Returns the newest matches (oldest first), and whether there were too many to return them all.
+fn search_outputs(
+  outputs: &[(String, String, OutputSource)],
+  pattern: &regex::Regex,
+  since_secs: Option<u64>,
+) -> (Vec<LogMatch>, bool) {
+  let options = LogOptions {
+    since_secs,
+    ..Default::default()
+  };
+  let now_ms = LogLine::new(OutputStream::Stdout, b"").time_ms;
+  // The oldest match we're holding on to is on top, ready to make way for a newer one.
+  let mut newest = std::collections::BinaryHeap::new();
+  let mut found = 0;
+  let mut truncated = false;
+  for (process_name, name, output) in outputs {
+    output.for_each_line(|line| {
+      if !line.matches(&options, now_ms) || !pattern.is_match(&line.text) {
+        return;
+      }
+      newest.push(std::cmp::Reverse(FoundMatch {
+        order: (line.time_ms, found),
+        line:  LogMatch {
+          process_name: process_name.clone(),
+          name:         name.clone(),
+          time_ms:      line.time_ms,
+          stream:       line.stream,
+          text:         line.text,
+        },
+      }));
+      found += 1;
+      if newest.len() > MAX_SEARCH_MATCHES {
+        newest.pop();
+        truncated = true;
+      }
+    });
+  }
+  // Sorting by the reversed order puts the newest first.
+  let matches = newest.into_sorted_vec().into_iter().rev().map(|found| found.0.line).collect();
+  (matches, truncated)
+}
+
+/// The end of a spool's output, kept in memory.
+#[derive(Default)]
+struct SpoolTail {
+  lines: VecDeque<LogLine>,
+  bytes: usize,
+}
+
+/// What a spool's writer task is sent, in order.
+enum SpoolMessage {
+  Line(LogLine),
+  /// One of the attached streams has ended.
+  EndOfStream,
+  /// Asks for the output so far (formatted with the options), and a receiver for what follows.
+  Follow(
+    LogOptions,
+    tokio::sync::oneshot::Sender<(String, tokio::sync::broadcast::Receiver<LogLine>)>,
+  ),
+}
+
+/// The output of a process (or hook), as lines. The end of it is kept in memory, and with log
+/// files, all of it (up to the size cap) also goes to disk.
+struct SpooledOutput {
+  tail:         Mutex<SpoolTail>,
+  /// The directory of the log files we're writing, until writing to them fails, after which we
+  /// only have the tail.
+  log_dir:      Mutex<Option<PathBuf>>,
+  /// Lines go to a writer task, which owns the log files and writes to them off the runtime.
+  messages:     tokio::sync::mpsc::UnboundedSender<SpoolMessage>,
+  /// Gets each line once it's written, for following the output.
+  updates:      tokio::sync::broadcast::Sender<LogLine>,
+  /// How many attached streams the writer task hasn't seen the end of yet.
+  open_streams: tokio::sync::watch::Sender<usize>,
+  /// Another spool that gets a copy of every line, like a hook step's process.
+  copy_to:      Option<Arc<SpooledOutput>>,
+}
+
+impl SpooledOutput {
+  /// Makes a spool that's only kept in memory.
+  fn new() -> Arc<Self> {
+    Self::with_log_files(None, None)
+  }
+
+  /// Makes a spool that's kept in memory, and copied into another one.
+  fn copying_to(other: &Arc<SpooledOutput>) -> Arc<Self> {
+    Self::with_log_files(None, Some(other.clone()))
+  }
+
+  fn with_log_files(log_files: Option<LogFiles>, copy_to: Option<Arc<SpooledOutput>>) -> Arc<Self> {
+    let (messages, receiver) = tokio::sync::mpsc::unbounded_channel();
+    let this = Arc::new(Self {
+      tail: Mutex::new(SpoolTail::default()),
+      log_dir: Mutex::new(log_files.as_ref().map(|files| files.dir.clone())),
+      messages,
+      updates: tokio::sync::broadcast::Sender::new(FOLLOW_BUFFERED_LINES),
+      open_streams: tokio::sync::watch::Sender::new(0),
+      copy_to,
+    });
+    tokio::spawn(Self::run_writer(Arc::downgrade(&this), receiver, log_files));
+    this
+  }
+
+  /// Handles a spool's messages one at a time, so that a follower's output so far (read back from
+  /// the log files) ends exactly where its updates begin. Runs until the spool is dropped.
+  async fn run_writer(
+    this: std::sync::Weak<Self>,
+    mut receiver: tokio::sync::mpsc::UnboundedReceiver<SpoolMessage>,
+    mut log_files: Option<LogFiles>,
+  ) {
+    let mut next = None;
+    loop {
+      let message = match next.take() {
+        Some(message) => message,
+        None => match receiver.recv().await {
+          Some(message) => message,
+          None => return,
+        },
+      };
+      let Some(this) = this.upgrade() else {
+        return;
+      };
+      match message {
+        SpoolMessage::Line(line) => {
+          // Write out every line that's waiting in one go.
+          let mut lines = vec![line];
+          while let Ok(message) = receiver.try_recv() {
+            match message {
+              SpoolMessage::Line(line) => lines.push(line),
+              message => {
+                next = Some(message);
+                break;
+              }
+            }
+          }
+          if let Some(mut files) = log_files.take() {
+            let records: String = lines.iter().map(LogLine::to_record).collect();
+            let written = tokio::task::spawn_blocking(move || {
+              let result = files.write(records.as_bytes());
+              (files, result)
+            })
+            .await;
+            match written {
+              Ok((files, Ok(()))) => log_files = Some(files),
+              Ok((files, Err(e))) => {
+                log_event(LogEvent::Warning {
+                  msg: format!("Failed to write to {:?}, so giving up on it: {:#}", files.dir, e),
+                });
+                *this.log_dir.lock().unwrap() = None;
+              }
+              Err(e) => {
+                log_event(LogEvent::Warning {
+                  msg: format!("Failed to write log files, so giving up on them: {}", e),
+                });
+                *this.log_dir.lock().unwrap() = None;
+              }
+            }
+          }
+          let mut tail = this.tail.lock().unwrap();
+          for line in lines {
+            tail.bytes += line.text.len();
+            tail.lines.push_back(line.clone());
+            // This only fails if nobody is following.
+            this.updates.send(line).ok();
+          }
+          while tail.bytes > MEMORY_TAIL_BYTES {
+            let Some(dropped) = tail.lines.pop_front() else {
+              break;
+            };
+            tail.bytes -= dropped.text.len();
+          }
+        }
+        SpoolMessage::EndOfStream => this.open_streams.send_modify(|n| *n -= 1),
+        SpoolMessage::Follow(options, reply) => {
+          let output = match this.log_dir() {
+            Some(dir) => tokio::task::spawn_blocking(move || try_read_log_dir(&dir)).await.ok(),
+            None => None,
+          };
+          let output = match output.flatten() {
+            Some(lines) => format_lines(lines.iter(), &options),
+            None => format_lines(this.tail.lock().unwrap().lines.iter(), &options),
+          };
+          reply.send((output, this.updates.subscribe())).ok();
+        }
+      }
+    }
+  }
+
+  /// Makes a spool that writes to the given version's log files, or that's only kept in memory if
+  /// they can't be opened.
+  fn open(name: &str, info: &LogDirInfo, logs: &LogsSpec) -> Arc<Self> {
+    match LogFiles::open(name, info, logs) {
+      Ok(log_files) => Self::with_log_files(Some(log_files), None),
+      Err(e) => {
+        log_event(LogEvent::Warning {
+          msg: format!("Only keeping the end of {}'s output in memory: {:#}", name, e),
+        });
+        Self::new()
+      }
+    }
+  }
+
+  /// Starts spooling a child's stdout and stderr.
+  fn attach(
+    self: &Arc<Self>,
+    stdout: impl AsyncRead + Unpin + Send + 'static,
+    stderr: impl AsyncRead + Unpin + Send + 'static,
+  ) {
+    fn launch<T>(this: Arc<SpooledOutput>, stream: OutputStream, mut reader: T)
+    where
+      T: AsyncRead + Unpin + Send + 'static,
+    {
+      use tokio::io::AsyncReadExt;
+      tokio::spawn(async move {
+        let mut buf = [0; 4096];
+        let mut partial = Vec::new();
+        loop {
+          let n = match reader.read(&mut buf).await {
+            Ok(n) => n,
+            Err(e) => {
+              log_event(LogEvent::Warning {
+                msg: format!("Failed to read process output, so giving up on it: {}", e),
+              });
+              0
+            }
+          };
+          if n == 0 {
+            break;
+          }
+          partial.extend_from_slice(&buf[..n]);
+          while let Some(newline) = partial.iter().position(|&b| b == b'\n') {
+            this.write(LogLine::new(stream, &partial[..newline]));
+            partial.drain(..=newline);
+          }
+          // Break up overly long lines, rather than buffering them indefinitely.
+          if partial.len() >= MAX_LINE_BYTES {
+            this.write(LogLine::new(stream, &partial));
+            partial.clear();
+          }
+        }
+        if !partial.is_empty() {
+          this.write(LogLine::new(stream, &partial));
+        }
+        this.messages.send(SpoolMessage::EndOfStream).ok();
+      });
+    }
+    self.open_streams.send_modify(|n| *n += 2);
+    launch(self.clone(), OutputStream::Stdout, stdout);
+    launch(self.clone(), OutputStream::Stderr, stderr);
+  }
+
+  fn write(&self, line: LogLine) {
+    if let Some(other) = &self.copy_to {
+      other.write(line.clone());
+    }
+    // The writer task lasts as long as we do.
+    self.messages.send(SpoolMessage::Line(line)).ok();
+  }
+
+  /// Waits (up to a timeout) until we've read and written everything from the attached streams, as
+  /// a process that just exited may have output we haven't read yet.
+  async fn wait_for_eof(&self, timeout: std::time::Duration) {
+    let mut open_streams = self.open_streams.subscribe();
+    tokio::time::timeout(timeout, open_streams.wait_for(|n| *n == 0)).await.ok();
+  }
+
+  /// Gets the output we have, reading it back from disk if we can.
+  fn get(&self, options: &LogOptions) -> String {
+    format_lines(self.lines().iter(), options)
+  }
+
+  /// Gets every line we have, oldest first, reading them back from disk if we can. Lines still on
+  /// their way to the log files are left out.
+  fn lines(&self) -> Vec<LogLine> {
+    let dir = self.log_dir();
+    match dir.as_deref().and_then(try_read_log_dir) {
+      Some(lines) => lines,
+      None => self.tail.lock().unwrap().lines.iter().cloned().collect(),
+    }
+  }
+
+  fn log_dir(&self) -> Option<PathBuf> {
+    self.log_dir.lock().unwrap().clone()
+  }
+
+  /// Gets the output we have so far, along with a receiver for the lines that follow.
+  async fn follow(
+    &self,
+    options: &LogOptions,
+  ) -> Result<(String, tokio::sync::broadcast::Receiver<LogLine>), Error> {
+    let (reply, receiver) = tokio::sync::oneshot::channel();
+    self
+      .messages
+      .send(SpoolMessage::Follow(options.clone(), reply))
+      .map_err(|_| anyhow!("Output writer is gone"))?;
+    Ok(receiver.await?)
+  }
+
+  /// Gets (roughly) the last `max_bytes` of output, up to what's kept in memory.
+  fn tail(&self, max_bytes: usize) -> String {
+    let tail = self.tail.lock().unwrap();
+    let mut start = tail.lines.len();
+    let mut bytes = 0;
+    while start > 0 && bytes + tail.lines[start - 1].text.len() < max_bytes {
+      start -= 1;
+      bytes += tail.lines[start].text.len() + 1;
+    }
+    format_lines(tail.lines.range(start..), &LogOptions::default())
+  }
+}
+
+/// The output of one step of a process's before command, or of one of its lifecycle hooks. This
+/// also goes into the process's own output, between markers, so that it's kept in the log files.
+struct HookStep {
+  /// What to call the step in logs, like "before step 1/2".
+  label:          String,
+  command:        String,
+  output:         Arc<SpooledOutput>,
+  process_output: Arc<SpooledOutput>,
+  /// How the step finished, once it has.
+  result:         Mutex<Option<String>>,
+}
+
+impl HookStep {
+  fn new(label: String, command: &str, process_output: &Arc<SpooledOutput>) -> Arc<Self> {
+    Arc::new(Self {
+      label,
+      command: command.to_string(),
+      output: SpooledOutput::copying_to(process_output),
+      process_output: process_output.clone(),
+      result: Mutex::new(None),
+    })
+  }
+
+  fn mark_in_process_output(&self, note: String) {
+    let marker = format!("--- launcho: {} ---", note);
+    self.process_output.write(LogLine::new(OutputStream::Stderr, marker.as_bytes()));
+  }
+
+  fn finish(&self, result: String) {
+    self.mark_in_process_output(format!("{} finished ({})", self.label, result));
+    *self.result.lock().unwrap() = Some(result);
+  }
+}
+
+/// Returns the start time of a process (in clock ticks since boot), used to detect PID reuse.
+fn get_process_start_time(pid: u32) -> Option<u64> {
+  let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+  // The command name is in parentheses, and may itself contain spaces or parentheses.
+  let after_command = &stat[stat.rfind(')')? + 1..];
+  // The start time is field 22, and the fields after the command name start at field 3.
+  after_command.split_whitespace().nth(22 - 3)?.parse().ok()
+}
+
+fn is_pid_alive(pid: u32, start_time: u64) -> bool {
+  get_process_start_time(pid) == Some(start_time)
+}
+
+/// A process we're managing, which is either our own child, or one that was launched by a
+/// previous launcho server and adopted by us. We can't wait on adopted processes, so we can
+/// only poll to see if they're alive, and never learn their exit codes.
+/// Before a process is spawned, this holds the task preparing its working directory.
+enum ProcessHandle {
+  Preparing(tokio::task::JoinHandle<Result<(), Error>>),
+  Child(tokio::process::Child),
+  Adopted { pid: u32, start_time: u64 },
+}
+
+impl ProcessHandle {
+  fn id(&self) -> Option<u32> {
+    match self {
+      ProcessHandle::Preparing(_) => None,
+      ProcessHandle::Child(child) => child.id(),
+      ProcessHandle::Adopted { pid, start_time } => is_pid_alive(*pid, *start_time).then_some(*pid),
+    }
+  }
+
+  /// Returns how the process exited, if it has. Processes that are still being prepared never
+  /// count as exited.
+  fn try_wait(&mut self) -> Result<Option<ExitInfo>, Error> {
+    Ok(match self {
+      ProcessHandle::Preparing(_) => None,
+      ProcessHandle::Child(child) => child.try_wait()?.map(ExitInfo::from),
+      ProcessHandle::Adopted { pid, start_time } =>
+        (!is_pid_alive(*pid, *start_time)).then_some(ExitInfo::UNKNOWN),
+    })
+  }
+
+  fn start_kill(&mut self) -> std::io::Result<()> {
+    match self {
+      ProcessHandle::Preparing(task) => {
+        task.abort();
+        Ok(())
+      }
+      ProcessHandle::Child(child) => child.start_kill(),
+      ProcessHandle::Adopted { .. } => {
+        if let Some(pid) = self.id() {
+          if unsafe { libc::kill(pid as i32, libc::SIGKILL) } != 0 {
+            return Err(std::io::Error::last_os_error());
+          }
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+/// How a process exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ExitInfo {
+  /// The exit code, or -1 if the process was killed by a signal or we don't know.
+  exit_status: i32,
+  signal:      Option<i32>,
+  core_dumped: bool,
+}
+
+impl ExitInfo {
+  /// For processes we can't wait on, like adopted ones, or ones that never started.
+  const UNKNOWN: Self = Self {
+    exit_status: -1,
+    signal:      None,
+    core_dumped: false,
+  };
+}
+
+impl From<std::process::ExitStatus> for ExitInfo {
+  fn from(status: std::process::ExitStatus) -> Self {
+    use std::os::unix::process::ExitStatusExt;
+    Self {
+      exit_status: status.code().unwrap_or(-1),
+      signal:      status.signal(),
+      core_dumped: status.core_dumped(),
+    }
+  }
+}
+
+/// Where a sunsetting process is in its graceful shutdown.
+enum ShutdownPhase {
+  /// Traffic has been moved away, and we're waiting for open connections to close.
+  Draining { since: std::time::Instant },
+  /// Connections have drained, and we're waiting for the pre-stop hook to finish.
+  PreStop { hook: Arc<HookStep> },
+  /// The stop signal has been sent, and we're waiting for the process to exit.
+  Stopping { since: std::time::Instant },
+  /// SIGKILL has been sent.
+  Killed,
+}
+
+struct RunningProcessEntry {
+  status:            ProcessStatus,
+  shutdown_phase:    Option<ShutdownPhase>,
+  approx_start:      std::time::Instant,
+  approx_conn_count: i32,
+  process:           ProcessHandle,
+  name:              String,
+  /// The name of the spec we're a version of.
+  process_name:      String,
+  /// Which replica slot (in 0..replicas) this entry fills.
+  replica:           usize,
+  cwd:               PathBuf,
+  /// If the cwd is a temporary directory we created, which should be removed along with us.
+  temp_cwd:          bool,
+  /// Our own cgroup, which is removed along with us.
+  cgroup:            Option<PathBuf>,
+  /// Maps service name to port number.
+  port_allocations:  HashMap<String, u16>,
+  output:            Arc<SpooledOutput>,
+  hook_steps:        Vec<Arc<HookStep>>,
+  readiness:         ProbeState,
+  liveness:          ProbeState,
+  /// Whether the readiness probe is passing, so that we should get traffic while running.
+  ready:             bool,
+  /// Whether we've become running yet, and so kicked off the post-start hook.
+  ran_post_start:    bool,
+  /// Made just before spawning, if the process uses the notify protocol.
+  notify:            Option<NotifySocket>,
+}
+
+impl RunningProcessEntry {
+  fn new(
+    name: String,
+    process_name: String,
+    preparation: tokio::task::JoinHandle<Result<(), Error>>,
+    (output, hook_steps): (Arc<SpooledOutput>, Vec<Arc<HookStep>>),
+    replica: usize,
+    (cwd, temp_cwd): (PathBuf, bool),
+    port_allocations: HashMap<String, u16>,
+  ) -> Self {
+    Self {
+      status: ProcessStatus::Preparing,
+      shutdown_phase: None,
+      approx_start: std::time::Instant::now(),
+      approx_conn_count: 0,
+      process: ProcessHandle::Preparing(preparation),
+      name,
+      process_name,
+      replica,
+      cwd,
+      temp_cwd,
+      cgroup: None,
+      port_allocations,
+      output,
+      hook_steps,
+      readiness: ProbeState::default(),
+      liveness: ProbeState::default(),
+      ready: true,
+      ran_post_start: false,
+      notify: None,
+    }
+  }
+
+  fn adopt(record: PersistedProcess, logs: &LogsSpec) -> Self {
+    let info = LogDirInfo {
+      process_name: record.process_name.clone(),
+      replica:      record.replica,
+    };
+    let output = SpooledOutput::open(&record.name, &info, logs);
+    // Processes from before we had output FIFOs are still writing to the last server's pipes.
+    match OutputFifos::new(&record.name).and_then(|fifos| fifos.open_for_reading()) {
+      Ok((stdout, stderr)) => {
+        output.write(LogLine::new(OutputStream::Stderr, b"(launcho server restarted)"));
+        output.attach(stdout, stderr);
+      }
+      Err(_) => output.write(LogLine::new(
+        OutputStream::Stderr,
+        b"(launcho server restarted, and can no longer read this process's output)",
+      )),
+    }
+    Self {
+      status: ProcessStatus::Starting,
+      shutdown_phase: None,
+      approx_start: std::time::Instant::now(),
+      approx_conn_count: 0,
+      process: ProcessHandle::Adopted {
+        pid:        record.pid,
+        start_time: record.start_time,
+      },
+      name: record.name,
+      process_name: record.process_name,
+      replica: record.replica,
+      cwd: record.cwd,
+      temp_cwd: record.temp_cwd,
+      cgroup: record.cgroup,
+      port_allocations: record.port_allocations,
+      output,
+      hook_steps: Vec::new(),
+      readiness: ProbeState::default(),
+      liveness: ProbeState::default(),
+      ready: true,
+      // This already happened before the launcho server restarted.
+      ran_post_start: true,
+      notify: None,
+    }
+  }
+
+  fn update_status(&mut self, status: ProcessStatus) {
+    log_event(LogEvent::StatusChange {
+      name: self.name.clone(),
+      status,
+      crash_report: None,
+    });
+    self.status = status;
+  }
+
+  /// Marks us as exited, noting if we ran out of memory, and killing anything we left behind in
+  /// our cgroup. If we exited unexpectedly, this also records a crash report.
+  fn mark_exited(&mut self, exit: ExitInfo) {
+    let mut reason = ExitReason::Normal;
+    if self.cgroup.as_deref().is_some_and(cgroup::was_oom_killed) {
+      log_event(LogEvent::OutOfMemory {
+        name: self.name.clone(),
+      });
+      reason = ExitReason::OutOfMemory;
+    }
+    self.kill_leftovers();
+    self.remove_output_fifos();
+    let approx_time = get_unix_time();
+    // We only count it as a crash if the process was actually up, and we didn't stop it ourselves.
+    // Without an exit status, like for adopted processes, we can't tell if it crashed.
+    let crashed = self.shutdown_phase.is_none()
+      && exit != ExitInfo::UNKNOWN
+      && matches!(
+        self.status,
+        ProcessStatus::Starting | ProcessStatus::Running | ProcessStatus::Unhealthy
+      )
+      && (exit.exit_status != 0 || exit.signal.is_some());
+    let status = ProcessStatus::Exited {
+      exit_status: exit.exit_status,
+      approx_time,
+      reason,
+    };
+    if !crashed {
+      self.update_status(status);
+      return;
+    }
+    // The crash report waits on the rest of our output, so we finish it off in the background,
+    // rather than holding up housekeeping (and so the lock).
+    self.status = status;
+    let mut report = CrashReport {
+      name: self.name.clone(),
+      process_name: self.process_name.clone(),
+      exit_status: exit.exit_status,
+      signal: exit.signal,
+      core_dumped: exit.core_dumped,
+      approx_time,
+      run_time_secs: self.approx_start.elapsed().as_secs(),
+      output_tail: String::new(),
+    };
+    let output = self.output.clone();
+    tokio::spawn(async move {
+      output.wait_for_eof(OUTPUT_EOF_TIMEOUT).await;
+      report.output_tail = output.tail(CRASH_REPORT_OUTPUT_BYTES);
+      record_crash(report.clone());
+      log_event(LogEvent::StatusChange {
+        name: report.name.clone(),
+        status,
+        crash_report: Some(report),
+      });
+    });
+  }
+
+  /// Kills us, and marks us as having failed to start. We don't wait for the kill to go through.
+  fn mark_failed_to_start(&mut self, reason: StartFailure) {
+    if let Err(e) = self.process.start_kill() {
+      log_event(LogEvent::Error {
+        msg: format!("Failed to kill {}: {}", self.name, e),
+      });
+    }
+    self.kill_leftovers();
+    self.remove_output_fifos();
+    self.update_status(ProcessStatus::FailedToStart {
+      reason,
+      approx_time: get_unix_time(),
+    });
+  }
+
+  /// Runs one of our lifecycle hooks in the background, logging how it went. The returned step
+  /// gets its result once the hook is done.
+  fn spawn_lifecycle_hook(
+    &mut self,
+    spec: &ProcessSpec,
+    hook: LifecycleHook,
+    command: &str,
+  ) -> Arc<HookStep> {
+    let label = match hook {
+      LifecycleHook::PostStart => "post_start hook",
+      LifecycleHook::PreStop => "pre_stop hook",
+    };
+    let step = HookStep::new(label.to_string(), command, &self.output);
+    self.hook_steps.push(step.clone());
+    tokio::spawn({
+      let step = step.clone();
+      let name = self.name.clone();
+      let spec = spec.clone();
+      let cwd = self.cwd.clone();
+      let cgroup = self.cgroup.clone();
+      let port_allocations = self.port_allocations.clone();
+      async move {
+        let timeout = spec.hooks.timeout.0;
+        let run = run_hook_step(&step, &spec, &cwd, cgroup.as_deref(), &port_allocations);
+        let (success, result) = match tokio::time::timeout(timeout, run).await {
+          Ok(Ok(status)) => (status.success(), status.to_string()),
+          Ok(Err(e)) => (false, format!("failed to run: {:#}", e)),
+          Err(_) => (false, format!("timed out after {:?}", timeout)),
+        };
+        log_event(LogEvent::HookFinished {
+          name,
+          hook,
+          success,
+          result: result.clone(),
+        });
+        step.finish(result);
+      }
+    });
+    step
+  }
+
+  fn send_stop_signal(&mut self, spec: &ProcessSpec) {
+    log_event(LogEvent::SendStopSignal {
+      name:             self.name.clone(),
+      signal:           spec.shutdown.stop_signal.clone(),
+      open_connections: self.approx_conn_count,
+    });
+    match (self.process.id(), parse_signal(&spec.shutdown.stop_signal)) {
+      (Some(pid), Ok(signal)) => unsafe {
+        libc::kill(pid as i32, signal);
+      },
+      (None, _) => log_event(LogEvent::Error {
+        msg: format!("Failed to send stop signal to {}: no PID available", self.name),
+      }),
+      (_, Err(e)) => log_event(LogEvent::Error {
+        msg: format!("Failed to send stop signal to {}: {}", self.name, e),
+      }),
+    }
+    self.shutdown_phase = Some(ShutdownPhase::Stopping {
+      since: std::time::Instant::now(),
+    });
+  }
+
+  fn kill_leftovers(&self) {
+    if let Some(cgroup) = &self.cgroup {
+      if let Err(e) = cgroup::kill_all(cgroup) {
+        log_event(LogEvent::Warning {
+          msg: format!("Failed to kill leftover processes of {}: {:#}", self.name, e),
+        });
+      }
+    }
+  }
+
+  /// Removes our output FIFOs, once the process is gone. Any ends we already have open still work.
+  fn remove_output_fifos(&self) {
+    if let Ok(fifos) = OutputFifos::new(&self.name) {
+      fifos.remove();
+    }
+  }
+
+  /// Removes the temporary cwd, cgroup and output FIFOs, once we're done with this entry.
+  fn remove_leftovers(&self) {
+    self.remove_output_fifos();
+    if self.temp_cwd {
+      if let Err(e) = std::fs::remove_dir_all(&self.cwd) {
+        log_event(LogEvent::Warning {
+          msg: format!("Failed to remove {:?}: {}", self.cwd, e),
+        });
+      }
+    }
+    if let Some(cgroup) = &self.cgroup {
+      if let Err(e) = cgroup::remove(cgroup) {
+        log_event(LogEvent::Warning {
+          msg: format!("{:#}", e),
+        });
+      }
+    }
+  }
+
+  fn probe_state_mut(&mut self, probe: Probe) -> &mut ProbeState {
+    match probe {
+      Probe::Readiness => &mut self.readiness,
+      Probe::Liveness => &mut self.liveness,
+    }
+  }
 
-const LOG_MAX_SIZE: usize = 1000;
-static LOG_EVENTS: Mutex<VecDeque<LogEvent>> = Mutex::new(VecDeque::new());
+  fn set_ready(&mut self, ready: bool) {
+    if self.ready != ready {
+      log_event(LogEvent::ReadinessChange {
+        name: self.name.clone(),
+        ready,
+      });
+      self.ready = ready;
+    }
+  }
 
-pub fn log_event(event: LogEvent) {
-  eprintln!("\x1b[93m[Event]\x1b[0m {:?}", event);
-  let mut events = LOG_EVENTS.lock().unwrap();
-  events.push_back(event);
-  while events.len() > LOG_MAX_SIZE {
-    events.pop_front();
+  /// Counts the result of a probe, and acts on it once enough checks in a row agree.
+  fn record_probe_result(&mut self, spec: &ProcessSpec, probe: Probe, healthy: bool) {
+    let Some(check) = probe.check(spec) else {
+      return;
+    };
+    let state = self.probe_state_mut(probe);
+    state.record(healthy);
+    let passing = state.successes >= check.success_threshold;
+    let failing = state.failures >= check.failure_threshold;
+    match (probe, self.status) {
+      (Probe::Readiness, ProcessStatus::Starting) if passing =>
+        self.update_status(ProcessStatus::Running),
+      // A plain health check gets us replaced, rather than just taken out of rotation.
+      (Probe::Readiness, ProcessStatus::Running) if failing && spec.readiness.is_none() =>
+        self.update_status(ProcessStatus::Unhealthy),
+      (Probe::Readiness, ProcessStatus::Running) if failing => self.set_ready(false),
+      (Probe::Readiness, ProcessStatus::Running) if passing => self.set_ready(true),
+      (Probe::Liveness, ProcessStatus::Running) if failing =>
+        self.update_status(ProcessStatus::Unhealthy),
+      _ => {}
+    }
   }
-}
 
-pub fn get_entire_log() -> Vec<LogEvent> {
-  LOG_EVENTS.lock().unwrap().iter().cloned().collect()
-}
+  /// Checks if we're done for good, including if we failed to start.
+  fn is_exited(&self) -> bool {
+    self.status.finished_time().is_some()
+  }
 
-#[derive(PartialEq, Eq)]
-pub enum RateLimitResult {
-  Success,
-  RateLimited,
-  Backoff,
-}
+  /// How long the process has been running, or how long ago it exited.
+  fn run_time(&self) -> std::time::Duration {
+    match self.status.finished_time() {
+      Some(approx_time) =>
+        std::time::Duration::from_secs(get_unix_time().saturating_sub(approx_time)),
+      None => self.approx_start.elapsed(),
+    }
+  }
 
-impl RateLimitResult {
-  pub fn is_success(&self) -> bool {
-    match self {
-      RateLimitResult::Success => true,
-      _ => false,
+  /// If our preparation has finished, spawns the main command, or marks us as exited if the
+  /// preparation failed.
+  async fn finish_preparation(&mut self, spec: &ProcessSpec) {
+    let ProcessHandle::Preparing(preparation) = &mut self.process else {
+      return;
+    };
+    if !preparation.is_finished() {
+      return;
     }
+    let mut result = match preparation.await {
+      Ok(result) => result,
+      // We cancel the preparation of processes we no longer want.
+      Err(e) if e.is_cancelled() => Err(anyhow!("Preparation cancelled")),
+      Err(e) => Err(e.into()),
+    };
+    if self.status == ProcessStatus::Preparing {
+      if result.is_ok() && uses_notify(spec) {
+        match NotifySocket::bind(&self.name, spec, false) {
+          Ok(notify) => self.notify = Some(notify),
+          Err(e) => result = Err(e),
+        }
+      }
+      match result.and_then(|()| GlobalState::spawn_process(spec, self)) {
+        Ok(process) => {
+          self.process = ProcessHandle::Child(process);
+          self.approx_start = std::time::Instant::now();
+          self.update_status(ProcessStatus::Starting);
+          return;
+        }
+        Err(e) => log_event(LogEvent::Error {
+          msg: format!("Failed to launch process {}: {:#}", self.name, e),
+        }),
+      }
+    }
+    self.mark_exited(ExitInfo::UNKNOWN);
   }
-}
 
-pub struct RateLimit {
-  prefix:           &'static str,
-  duration:         std::time::Duration,
-  backoff_duration: std::time::Duration,
-  max_attempts:     usize,
+  fn to_persisted(&self, spec: &ProcessSpec) -> Option<PersistedProcess> {
+    let pid = self.process.id()?;
+    Some(PersistedProcess {
+      pid,
+      start_time: get_process_start_time(pid)?,
+      process_name: spec.name.clone(),
+      name: self.name.clone(),
+      replica: self.replica,
+      spec_hash: spec_hash(spec),
+      port_allocations: self.port_allocations.clone(),
+      cwd: self.cwd.clone(),
+      temp_cwd: self.temp_cwd,
+      cgroup: self.cgroup.clone(),
+    })
+  }
 }
 
-static RATE_LIMITING: Mutex<Option<HashMap<String, Vec<std::time::Instant>>>> = Mutex::new(None);
-
-macro_rules! get_rate_limiting {
-  ($map:ident) => {
-    let mut map_guard = RATE_LIMITING.lock().unwrap();
-    let $map = match map_guard.as_mut() {
-      Some(map) => map,
-      None => {
-        *map_guard = Some(HashMap::new());
-        map_guard.as_mut().unwrap()
-      }
-    };
+/// Identifies the version of a spec, leaving out the replica count as `is_same_version` does, so
+/// that scaling doesn't stop a restarted server from adopting.
+fn spec_hash(spec: &ProcessSpec) -> String {
+  let normalized = ProcessSpec {
+    replicas: 1,
+    ..spec.clone()
   };
+  sha256::digest(serde_json::to_string(&normalized).unwrap())
+}
+
+/// What we record about each live process, so that a restarted server can deal with them.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct PersistedProcess {
+  pid:              u32,
+  start_time:       u64,
+  process_name:     String,
+  name:             String,
+  replica:          usize,
+  /// We don't store the spec itself, as it has secrets substituted into it.
+  spec_hash:        String,
+  port_allocations: HashMap<String, u16>,
+  cwd:              PathBuf,
+  temp_cwd:         bool,
+  #[serde(default)]
+  cgroup:           Option<PathBuf>,
 }
 
-// NB: count must always have the same value for a particular key.
-pub fn check_rate_limit(key: &str, rate_limit: &RateLimit) -> RateLimitResult {
-  let key = format!("{}:{}", rate_limit.prefix, key);
-  get_rate_limiting!(map);
-  let occurrences = map.entry(key).or_insert(Vec::new());
-  let now = std::time::Instant::now();
-  if let Some(last) = occurrences.last() {
-    if now.duration_since(*last) < rate_limit.duration {
-      return RateLimitResult::RateLimited;
+impl PersistedProcess {
+  /// Cleans up after a process that we aren't adopting, once it's dead.
+  async fn remove_leftovers(&self) {
+    if let Ok(fifos) = OutputFifos::new(&self.name) {
+      fifos.remove();
     }
-  }
-  if let Some(first) = occurrences.first() {
-    if occurrences.len() >= rate_limit.max_attempts
-      && now.duration_since(*first) < rate_limit.backoff_duration
-    {
-      return RateLimitResult::Backoff;
+    NotifySocket::remove_leftovers(&self.name);
+    if self.temp_cwd {
+      std::fs::remove_dir_all(&self.cwd).ok();
+    }
+    if let Some(cgroup) = &self.cgroup {
+      cgroup::kill_all(cgroup).ok();
+      // The kill takes a moment to go through, and the cgroup can't be removed until it has.
+      tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+      cgroup::remove(cgroup).ok();
     }
   }
-  RateLimitResult::Success
 }
 
-pub fn rate_limit_event(key: &str, rate_limit: &RateLimit) {
-  let key = format!("{}:{}", rate_limit.prefix, key);
-  get_rate_limiting!(map);
-  let occurrences = map.entry(key).or_insert(Vec::new());
-  let now = std::time::Instant::now();
-  occurrences.push(now);
-  while occurrences.len() > rate_limit.max_attempts {
-    occurrences.remove(0);
-  }
+struct ProcessSet {
+  pub running_versions: Vec<(ProcessSpec, RunningProcessEntry)>,
+  /// A dependency that isn't running yet, which is holding up launching.
+  pub waiting_on:       Option<String>,
+  pub restarts:         RestartHistory,
 }
 
-fn clear_launch_rate_limits() {
-  get_rate_limiting!(map);
-  map.retain(|k, _| !k.starts_with("launch:"));
+impl ProcessSet {
+  fn new() -> Self {
+    Self {
+      running_versions: Vec::new(),
+      waiting_on:       None,
+      restarts:         RestartHistory::default(),
+    }
+  }
 }
 
-struct SpooledOutput {
-  buffer: Mutex<Vec<u8>>,
+/// What the restart policy and backoff go by, and what status shows about them.
+#[derive(Default)]
+struct RestartHistory {
+  /// What we last launched into each replica slot.
+  replicas:      HashMap<usize, ReplicaState>,
+  /// When we relaunched a replica, going back over the restart window.
+  restart_times: VecDeque<std::time::Instant>,
+  /// The exit statuses of our most recently exited processes, oldest first.
+  recent_exits:  VecDeque<i32>,
 }
 
-impl SpooledOutput {
-  fn new(stdout: ChildStdout, stderr: ChildStderr) -> Arc<Self> {
-    let this = Arc::new(Self {
-      buffer: Mutex::new(Vec::new()),
-    });
-
-    fn launch<T>(this: Arc<SpooledOutput>, mut reader: T)
-    where
-      T: AsyncRead + Unpin + Send + Sync + 'static,
+impl RestartHistory {
+  fn note_restart(&mut self) {
+    let now = std::time::Instant::now();
+    while self.restart_times.front().is_some_and(|time| now.duration_since(*time) > RESTART_WINDOW)
     {
-      use tokio::io::AsyncReadExt;
-      tokio::spawn(async move {
-        let mut buf = [0; 4096];
-        loop {
-          let n = reader.read(&mut buf).await.unwrap();
-          if n == 0 {
-            break;
+      self.restart_times.pop_front();
+    }
+    self.restart_times.push_back(now);
+  }
+
+  fn restarts_in_window(&self) -> usize {
+    self.restart_times.iter().filter(|time| time.elapsed() <= RESTART_WINDOW).count()
+  }
+
+  /// Lets every replica relaunch right away, as if it had never been restarted.
+  fn clear_backoff(&mut self) {
+    for state in self.replicas.values_mut() {
+      state.restarts = 0;
+      if state.retry_at.is_some() {
+        state.retry_at = Some(std::time::Instant::now());
+      }
+    }
+  }
+
+  /// Describes replicas that are waiting to be relaunched, or won't be.
+  fn describe_replicas(&self, restart: RestartPolicy) -> Vec<String> {
+    let mut replicas: Vec<_> = self.replicas.iter().collect();
+    replicas.sort_by_key(|(replica, _)| **replica);
+    let mut descriptions = Vec::new();
+    for (replica, state) in replicas {
+      let Some(succeeded) = state.succeeded else {
+        continue;
+      };
+      let description = match state.retry_at {
+        Some(retry_at) => {
+          let wait = retry_at.saturating_duration_since(std::time::Instant::now());
+          match !succeeded && state.restarts > 0 {
+            true => format!("crash looping, next attempt in {}", format_rough_duration(wait)),
+            false => format!("restarting, next attempt in {}", format_rough_duration(wait)),
           }
-          let mut guard = this.buffer.lock().unwrap();
-          guard.extend_from_slice(&buf[..n]);
-          std::mem::drop(guard);
         }
-      });
+        None if restart == RestartPolicy::Never => "stopped, as restart is never".to_string(),
+        None if restart == RestartPolicy::OnFailure && succeeded =>
+          "stopped, as restart is on-failure".to_string(),
+        None => continue,
+      };
+      descriptions.push(format!("replica {}: {}", replica, description));
     }
-    launch(this.clone(), stdout);
-    launch(this.clone(), stderr);
-
-    this
+    descriptions
   }
 
-  fn get(&self) -> String {
-    let guard = self.buffer.lock().unwrap();
-    String::from_utf8_lossy(&guard).to_string()
+  /// Notes how a process ended, for deciding whether to relaunch its replica.
+  fn note_finished(&mut self, entry: &RunningProcessEntry) {
+    if let ProcessStatus::Exited { exit_status, .. } = entry.status {
+      if self.recent_exits.len() >= RECENT_EXITS_SHOWN {
+        self.recent_exits.pop_front();
+      }
+      self.recent_exits.push_back(exit_status);
+    }
+    let Some(state) = self.replicas.get_mut(&entry.replica) else {
+      return;
+    };
+    if state.name.as_ref() == Some(&entry.name) {
+      state.succeeded = Some(matches!(entry.status, ProcessStatus::Exited {
+        exit_status: 0,
+        reason: ExitReason::Normal,
+        ..
+      }));
+    }
   }
 }
 
-struct RunningProcessEntry {
-  status:            ProcessStatus,
-  approx_start:      std::time::Instant,
-  approx_conn_count: i32,
-  process:           tokio::process::Child,
-  name:              String,
-  _cwd:              PathBuf,
-  /// Maps service name to port number.
-  port_allocations:  HashMap<String, u16>,
-  output:            Arc<SpooledOutput>,
+/// The last launch into a replica slot, which the restart policy and backoff go by.
+struct ReplicaState {
+  spec:        ProcessSpec,
+  /// The launched process, unless launching failed.
+  name:        Option<String>,
+  launched_at: std::time::Instant,
+  /// How many relaunches in a row came before this launch.
+  restarts:    u32,
+  /// When the next launch may happen, once we've decided to relaunch.
+  retry_at:    Option<std::time::Instant>,
+  /// Whether the process exited successfully, once it has finished.
+  succeeded:   Option<bool>,
 }
 
-impl RunningProcessEntry {
-  fn new(
-    mut process: tokio::process::Child,
-    cwd: PathBuf,
-    port_allocations: HashMap<String, u16>,
-  ) -> Self {
-    let pid = process.id().unwrap_or(u32::MAX);
-    let name = format!("{}-{}-{}", make_random_word(), get_counter(), pid);
-    let stdout = process.stdout.take().unwrap();
-    let stderr = process.stderr.take().unwrap();
-    Self {
-      status: ProcessStatus::Starting,
-      approx_start: std::time::Instant::now(),
-      approx_conn_count: 0,
-      process,
-      name,
-      _cwd: cwd,
-      port_allocations,
-      output: SpooledOutput::new(stdout, stderr),
-    }
-  }
+/// One run of a job. Each run gets launched like a single replica process.
+struct JobRun {
+  trigger: JobTrigger,
+  spec:    ProcessSpec,
+  entry:   RunningProcessEntry,
 }
 
-struct ProcessSet {
-  pub running_versions: Vec<(ProcessSpec, RunningProcessEntry)>,
+struct JobState {
+  /// The spec we've last seen for this job, to detect changes.
+  seen_spec:           Option<JobSpec>,
+  /// The last minute (since the epoch) that we've checked the schedule for.
+  last_checked_minute: u64,
+  /// A run that has been triggered, but not started yet.
+  pending:             Option<JobTrigger>,
+  /// The current run (if any), and the history of finished runs, oldest first.
+  runs:                Vec<JobRun>,
 }
 
-impl ProcessSet {
+impl JobState {
   fn new() -> Self {
     Self {
-      running_versions: Vec::new(),
+      seen_spec:           None,
+      last_checked_minute: get_unix_time() / 60,
+      pending:             None,
+      runs:                Vec::new(),
     }
   }
+
+  fn is_running(&self) -> bool {
+    self.runs.iter().any(|run| !run.entry.is_exited())
+  }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -310,9 +2116,36 @@ struct SyncedGlobalState {
   target:              LaunchoTarget,
   clean_services:      HashSet<AppliedIpvsService>,
   processes_by_name:   HashMap<String, ProcessSet>,
+  jobs_by_name:        HashMap<String, JobState>,
   free_loopback_ports: VecDeque<u16>,
   allocated_ports:     HashSet<u16>,
   last_ipvs_state:     Option<ipvs::IpvsState>,
+  retention:           RetentionSpec,
+  logs:                LogsSpec,
+  /// What we last wrote to the processes file.
+  persisted_processes: Vec<PersistedProcess>,
+}
+
+impl SyncedGlobalState {
+  fn process_entries_mut(&mut self) -> impl Iterator<Item = &mut RunningProcessEntry> {
+    self
+      .processes_by_name
+      .values_mut()
+      .flat_map(|process_set| process_set.running_versions.iter_mut().map(|(_, entry)| entry))
+  }
+
+  /// Like `process_entries_mut`, but also includes job runs.
+  fn all_entries_mut(&mut self) -> impl Iterator<Item = &mut RunningProcessEntry> {
+    let job_run_entries = self
+      .jobs_by_name
+      .values_mut()
+      .flat_map(|job_state| job_state.runs.iter_mut().map(|run| &mut run.entry));
+    self
+      .processes_by_name
+      .values_mut()
+      .flat_map(|process_set| process_set.running_versions.iter_mut().map(|(_, entry)| entry))
+      .chain(job_run_entries)
+  }
 }
 
 fn allocate_port(
@@ -345,9 +2178,18 @@ fn release_port(
   free_loopback_ports.push_front(port);
 }
 
+/// Health checks that are running off of the global lock, and the results of those that finished.
+#[derive(Default)]
+struct ProbeResults {
+  /// Process names and probes with a check running, so that checks don't pile up.
+  in_flight: HashSet<(String, Probe)>,
+  /// Results in the order they came in, waiting for housekeeping to take them in.
+  finished:  Vec<(String, Probe, Result<bool, Error>)>,
+}
+
 struct GlobalState {
-  // Whoops, I no longer have any unsynced state. Should I remove this extra type?
-  synced: TokioMutex<SyncedGlobalState>,
+  synced:        TokioMutex<SyncedGlobalState>,
+  probe_results: Mutex<ProbeResults>,
 }
 
 impl GlobalState {
@@ -376,27 +2218,73 @@ impl GlobalState {
         LaunchoTarget::default()
       }
     };
-    let this = Self {
-      synced: TokioMutex::new(SyncedGlobalState {
+    Self {
+      synced:        TokioMutex::new(SyncedGlobalState {
         secrets,
         target_text,
         target,
         clean_services: HashSet::new(),
         processes_by_name: HashMap::new(),
+        jobs_by_name: HashMap::new(),
         free_loopback_ports,
         allocated_ports: HashSet::new(),
         last_ipvs_state: None,
+        retention: config.server.retention,
+        logs: config.server.logs,
+        persisted_processes: Vec::new(),
       }),
-    };
-    this
+      probe_results: Mutex::new(ProbeResults::default()),
+    }
   }
 
+  /// Allocates ports and a working directory for a new version of a process, and kicks off
+  /// preparing it in the background. Once that's done, housekeeping calls `spawn_process`.
   fn launch_process(
     &self,
     free_loopback_ports: &mut VecDeque<u16>,
     allocated_ports: &mut HashSet<u16>,
+    logs: &LogsSpec,
     process_spec: &ProcessSpec,
+    replica: usize,
   ) -> Result<RunningProcessEntry, Error> {
+    let (cwd, temp_cwd) = match &process_spec.cwd {
+      Some(cwd) => (PathBuf::from(cwd), false),
+      None => {
+        // This gets removed when the entry is garbage collected, or below if launching fails.
+        crate::already_exists_ok(std::fs::create_dir("/tmp/launcho-procs"))?;
+        let nonce: u64 = rand::random();
+        let path = PathBuf::from(format!("/tmp/launcho-procs/tmp-{:016x}", nonce));
+        crate::already_exists_ok(std::fs::create_dir(&path))?;
+        if let Err(e) = chown_to_process(process_spec, &path) {
+          std::fs::remove_dir(&path).ok();
+          return Err(e);
+        }
+        (path, true)
+      }
+    };
+    let remove_temp_cwd = || {
+      if temp_cwd {
+        std::fs::remove_dir_all(&cwd).ok();
+      }
+    };
+
+    // Give every process its own cgroup when we can, though it's only an error to go without
+    // if the process has limits. This gets removed when the entry is garbage collected.
+    let name = format!("{}-{}", make_random_word(), get_counter());
+    let cgroup = match cgroup::is_available() {
+      true => cgroup::create(&name, &process_spec.limits).map(Some),
+      false if process_spec.limits.is_empty() => Ok(None),
+      false =>
+        Err(anyhow!("Process {} has limits, but cgroup v2 isn't available", process_spec.name)),
+    };
+    let cgroup = match cgroup {
+      Ok(cgroup) => cgroup,
+      Err(e) => {
+        remove_temp_cwd();
+        return Err(e);
+      }
+    };
+
     // Allocate ports for the services.
     let mut port_allocations = HashMap::new();
     for service_name in &process_spec.receives {
@@ -406,128 +2294,253 @@ impl GlobalState {
           log_event(LogEvent::Error {
             msg: format!("Failed to allocate ports when launching process: {}", e),
           });
-          // FIXME: There are many other paths along which I leak ports, not just this one!
-          // I should avoid leaking ports in all of them.
           for port in port_allocations.values() {
             release_port(free_loopback_ports, allocated_ports, *port);
           }
+          if let Some(cgroup) = &cgroup {
+            cgroup::remove(cgroup).ok();
+          }
+          remove_temp_cwd();
           return Err(e);
         }
       };
       port_allocations.insert(service_name.clone(), port);
     }
 
-    let mut command = tokio::process::Command::new(&process_spec.command[0]);
-    let cwd = match &process_spec.cwd {
-      Some(cwd) => PathBuf::from(cwd),
-      None => {
-        // FIXME: Clean this stuff up.
-        crate::already_exists_ok(std::fs::create_dir(&"/tmp/launcho-procs"))?;
-        let nonce: u64 = rand::random();
-        let path = format!("/tmp/launcho-procs/tmp-{:016x}", nonce);
-        crate::already_exists_ok(std::fs::create_dir(&path))?;
-        command.current_dir(&path);
-        PathBuf::from(path)
-      }
+    let info = LogDirInfo {
+      process_name: process_spec.name.clone(),
+      replica,
     };
-    command.current_dir(&cwd);
-    // Unpack requested resources.
-    for resource_request in &process_spec.resources {
-      let target = cwd.join(&resource_request.file);
-      storage::copy_resource(&resource_request.id, &target)?;
-    }
-    // Perform the optional before command.
-    if let Some(before) = &process_spec.before {
-      let mut before_command = std::process::Command::new("sh");
-      before_command.arg("-c").arg(before).current_dir(&cwd);
-      if let Some(uid) = &process_spec.uid {
-        command.uid(uid.to_uid()?);
-      }
-      if let Some(gid) = &process_spec.gid {
-        command.gid(gid.to_uid()?);
-      }
-      for (key, value) in &process_spec.env {
-        command.env(key, value);
-      }
-      for (service_name, port) in &port_allocations {
-        command.env(&format!("SERVICE_PORT_{}", service_name.to_uppercase()), port.to_string());
-      }
-      match before_command.output() {
-        Ok(output) =>
-          if !output.status.success() {
-            log_event(LogEvent::Error {
-              msg: format!("Before command failed: {}", String::from_utf8_lossy(&output.stderr)),
-            });
-            return Err(anyhow!("Before command failed"));
-          },
-        Err(e) => {
-          log_event(LogEvent::Error {
-            msg: format!("Failed to run before command: {}", e),
-          });
-          return Err(anyhow!("Failed to run before command"));
+    let output = SpooledOutput::open(&name, &info, logs);
+    let hook_steps: Vec<_> = process_spec
+      .before
+      .iter()
+      .enumerate()
+      .map(|(i, command)| {
+        let label = format!("before step {}/{}", i + 1, process_spec.before.len());
+        HookStep::new(label, command, &output)
+      })
+      .collect();
+    let preparation = tokio::spawn({
+      let process_spec = process_spec.clone();
+      let cwd = cwd.clone();
+      let cgroup = cgroup.clone();
+      let port_allocations = port_allocations.clone();
+      let hook_steps = hook_steps.clone();
+      async move {
+        let timeout = process_spec.prepare_timeout.0;
+        let preparation =
+          prepare_process(&process_spec, &cwd, cgroup.as_deref(), &port_allocations, &hook_steps);
+        match tokio::time::timeout(timeout, preparation).await {
+          Ok(result) => result,
+          Err(_) => bail!("Preparation timed out after {:?}", timeout),
         }
       }
-    }
-    // FIXME: Maybe deduplicate this against the above.
-    if let Some(uid) = &process_spec.uid {
-      command.uid(uid.to_uid()?);
-    }
-    if let Some(gid) = &process_spec.gid {
-      command.gid(gid.to_uid()?);
-    }
-    command.args(&process_spec.command[1..]);
-    for (key, value) in &process_spec.env {
-      command.env(key, value);
-    }
-    for (service_name, port) in &port_allocations {
-      command.env(&format!("SERVICE_PORT_{}", service_name.to_uppercase()), port.to_string());
-    }
-    command.stdin(Stdio::null());
-    command.stdout(Stdio::piped());
-    command.stderr(Stdio::piped());
-    // FIXME: I should try to find a way to distinguish between the binary and cwd not being found.
-    let process = command
-      .spawn()
-      .with_context(|| format!("Failed to launch process {:?}", process_spec.command))?;
-    let entry = RunningProcessEntry::new(process, cwd, port_allocations.clone());
+    });
+    let mut entry = RunningProcessEntry::new(
+      name,
+      process_spec.name.clone(),
+      preparation,
+      (output, hook_steps),
+      replica,
+      (cwd, temp_cwd),
+      port_allocations,
+    );
+    entry.cgroup = cgroup;
     log_event(LogEvent::LaunchProcess {
       name: entry.name.clone(),
       process_name: process_spec.name.clone(),
-      port_allocations,
+      replica,
+      port_allocations: entry.port_allocations.clone(),
     });
     Ok(entry)
   }
 
-  async fn health_check(
-    &self,
+  /// Spawns the main command of a process that has finished preparing.
+  fn spawn_process(
     process_spec: &ProcessSpec,
     entry: &RunningProcessEntry,
-  ) -> Result<bool, Error> {
-    let Some(health_check_spec) = &process_spec.health else {
-      // If there's no health check, then the process is always considered healthy.
-      return Ok(true);
+  ) -> Result<tokio::process::Child, Error> {
+    let Some((program, args)) = process_spec.command.split_first() else {
+      bail!("{} has an empty command", process_spec.name);
     };
-    let service_port = *entry
-      .port_allocations
-      .get(&health_check_spec.service)
-      .ok_or_else(|| anyhow!("BUG: No port allocated for service {}", health_check_spec.service))?;
-    let maybe_slash = if health_check_spec.path.starts_with("/") {
-      ""
-    } else {
-      "/"
+    let mut command = match &process_spec.isolation {
+      None => {
+        let mut command = tokio::process::Command::new(program);
+        command.args(args);
+        set_identity(&mut command, process_spec, entry.cgroup.as_deref())?;
+        command
+      }
+      // The sandbox helper needs root to set up the sandbox, and switches identity itself.
+      Some(isolation) => {
+        let writable = process_spec
+          .resources
+          .iter()
+          .map(|resource| entry.cwd.join(&resource.file))
+          .filter(|path| !path.starts_with(&entry.cwd))
+          .chain(isolation.writable.iter().map(PathBuf::from))
+          .collect();
+        let sandbox_config = SandboxConfig {
+          isolation: isolation.clone(),
+          cwd: entry.cwd.clone(),
+          writable,
+          uid: process_spec.uid.as_ref().map(|uid| uid.to_uid()).transpose()?,
+          gid: process_spec.gid.as_ref().map(|gid| gid.to_gid()).transpose()?,
+          command: process_spec.command.clone(),
+        };
+        let mut command = sandbox_config.to_command()?;
+        if let Some(cgroup) = &entry.cgroup {
+          cgroup::enter_on_spawn(&mut command, cgroup, None, None)?;
+        }
+        command
+      }
     };
-    let client = reqwest::Client::builder().timeout(CHECK_TIMEOUT).build()?;
-    Ok(
-      match client
-        .get(format!("http://localhost:{}{}{}", service_port, maybe_slash, health_check_spec.path))
-        .send()
-        .await
+    configure_command(&mut command, process_spec, &entry.cwd, &entry.port_allocations)?;
+    if let Some(notify) = &entry.notify {
+      command.env("NOTIFY_SOCKET", &notify.address);
+      if let Some(check) = process_spec
+        .liveness
+        .as_ref()
+        .filter(|check| matches!(check.kind, HealthCheckKind::Notify {}))
       {
-        Ok(response) => response.status().is_success(),
-        Err(e) if e.is_connect() || e.is_timeout() => false,
-        Err(e) => return Err(e.into()),
-      },
-    )
+        command.env("WATCHDOG_USEC", check.interval.0.as_micros().to_string());
+      }
+    }
+    command.stdin(Stdio::null());
+    // Output goes through FIFOs where we can, so that the process can outlive this server. We
+    // open our ends while we still hold the process's, so that they don't read as ended.
+    let fifos = OutputFifos::new(&entry.name).and_then(|fifos| {
+      fifos.create()?;
+      let (stdout, stderr) = fifos.open_for_process()?;
+      Ok((stdout, stderr, fifos.open_for_reading()?))
+    });
+    let readers = match fifos {
+      Ok((stdout, stderr, readers)) => {
+        command.stdout(stdout);
+        command.stderr(stderr);
+        Some(readers)
+      }
+      Err(e) => {
+        log_event(LogEvent::Warning {
+          msg: format!(
+            "Using pipes for {}'s output, so it won't survive a server restart: {:#}",
+            entry.name, e
+          ),
+        });
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::piped());
+        None
+      }
+    };
+    // FIXME: I should try to find a way to distinguish between the binary and cwd not being found.
+    let mut process = command
+      .spawn()
+      .with_context(|| format!("Failed to launch process {:?}", process_spec.command))?;
+    match readers {
+      Some((stdout, stderr)) => entry.output.attach(stdout, stderr),
+      None => entry.output.attach(process.stdout.take().unwrap(), process.stderr.take().unwrap()),
+    }
+    Ok(process)
+  }
+
+  /// Starts any health checks that are due. Each runs concurrently as its own task, without
+  /// holding the lock, and leaves its result for housekeeping.
+  async fn start_due_health_checks(&'static self) {
+    let mut probes = Vec::new();
+    {
+      let mut synced = self.synced.lock().await;
+      for process_set in synced.processes_by_name.values_mut() {
+        for (spec, entry) in &mut process_set.running_versions {
+          for &probe in PROBES {
+            let Some(check) = probe.check(spec) else {
+              continue;
+            };
+            if !probe.runs_while(entry.status) {
+              continue;
+            }
+            let key = (entry.name.clone(), probe);
+            let started = entry.approx_start;
+            let state = entry.probe_state_mut(probe);
+            if !state.is_due(probe, check, started)
+              || !self.probe_results.lock().unwrap().in_flight.insert(key)
+            {
+              continue;
+            }
+            state.last_check = Some(std::time::Instant::now());
+            probes.push(HealthProbe {
+              name: entry.name.clone(),
+              probe,
+              check: check.clone(),
+              spec: spec.clone(),
+              cwd: entry.cwd.clone(),
+              port_allocations: entry.port_allocations.clone(),
+              notify: entry.notify.as_ref().map(|notify| notify.state.clone()),
+            });
+          }
+        }
+      }
+    }
+    for probe in probes {
+      tokio::spawn(async move {
+        let result = probe.run().await;
+        let mut probe_results = self.probe_results.lock().unwrap();
+        probe_results.in_flight.remove(&(probe.name.clone(), probe.probe));
+        probe_results.finished.push((probe.name, probe.probe, result));
+      });
+    }
+  }
+
+  /// Records our live processes (if they've changed), so that a restarted server can adopt or
+  /// terminate them.
+  fn persist_processes(
+    processes_by_name: &HashMap<String, ProcessSet>,
+    jobs_by_name: &HashMap<String, JobState>,
+    persisted_processes: &mut Vec<PersistedProcess>,
+  ) {
+    let mut live_processes = Vec::new();
+    for process_set in processes_by_name.values() {
+      for (spec, entry) in &process_set.running_versions {
+        if !entry.is_exited() {
+          live_processes.extend(entry.to_persisted(spec));
+        }
+      }
+    }
+    // Job runs are recorded too, though as they aren't processes in the target, a restarted
+    // server will always terminate them.
+    for job_state in jobs_by_name.values() {
+      for run in &job_state.runs {
+        if !run.entry.is_exited() {
+          live_processes.extend(run.entry.to_persisted(&run.spec));
+        }
+      }
+    }
+    if live_processes == *persisted_processes {
+      return;
+    }
+    let write = || -> Result<(), Error> {
+      let processes_path = get_processes_path()?;
+      let temp_path = processes_path.with_extension("json.tmp");
+      std::fs::write(&temp_path, serde_json::to_string(&live_processes)?)?;
+      std::fs::rename(&temp_path, &processes_path)?;
+      Ok(())
+    };
+    match write() {
+      Ok(()) => *persisted_processes = live_processes,
+      Err(e) => log_event(LogEvent::Error {
+        msg: format!("Failed to record live processes: {:#}", e),
+      }),
+    }
+  }
+
+  /// Deletes old log files. Logs outlive their entries, until they're too old.
+  async fn prune_logs(&self) -> Result<(), Error> {
+    let (logs, live_names) = {
+      let mut synced = self.synced.lock().await;
+      let live_names: HashSet<String> =
+        synced.all_entries_mut().map(|entry| entry.name.clone()).collect();
+      (synced.logs.clone(), live_names)
+    };
+    tokio::task::spawn_blocking(move || prune_logs(&logs, &live_names)).await?
   }
 
   async fn housekeeping(&self) -> Result<(), Error> {
@@ -536,12 +2549,20 @@ impl GlobalState {
       target,
       clean_services,
       processes_by_name,
+      jobs_by_name,
       free_loopback_ports,
       allocated_ports,
       last_ipvs_state,
+      retention,
+      logs,
+      persisted_processes,
       ..
     } = &mut *synced;
 
+    // Record our live processes first, so that nothing failing later on in housekeeping keeps them
+    // from being recorded.
+    Self::persist_processes(processes_by_name, jobs_by_name, persisted_processes);
+
     // Make sure we have all relevant process sets.
     for process in &target.processes {
       if !processes_by_name.contains_key(&process.name) {
@@ -561,8 +2582,8 @@ impl GlobalState {
         log_event(LogEvent::CreateIpvsService {
           spec: service.clone(),
         });
-        ipvs::delete_service(&service).ok();
-        ipvs::create_service(&service)?;
+        ipvs::delete_service(service).ok();
+        ipvs::create_service(service)?;
         clean_services.insert(key);
       }
     }
@@ -574,47 +2595,126 @@ impl GlobalState {
       specs.insert(&process_spec.name, process_spec);
     }
 
+    // Find which processes have a running version, for dependencies.
+    let running_processes: HashSet<String> = processes_by_name
+      .iter()
+      .filter(|(_, process_set)| {
+        process_set
+          .running_versions
+          .iter()
+          .any(|(_, entry)| entry.status == ProcessStatus::Running)
+      })
+      .map(|(process_name, _)| process_name.clone())
+      .collect();
+
     // Do upkeep on every process set.
     for (process_name, process_set) in processes_by_name.iter_mut() {
-      let target_spec = specs.get(process_name.as_str());
-      let most_recent_version = process_set.running_versions.last();
-      match (target_spec, most_recent_version) {
+      process_set.waiting_on = None;
+      match specs.get(process_name.as_str()) {
         // If we have no target spec then we should kill all running versions.
-        (None, _) =>
+        None =>
           for (_, version) in &mut process_set.running_versions {
-            if !matches!(version.status, ProcessStatus::Exited { .. }) {
+            if !version.is_exited() {
               log_event(LogEvent::Kill {
                 name: version.name.clone(),
               });
-              version.process.kill().await.ok();
+              version.process.start_kill().ok();
+              version.shutdown_phase = Some(ShutdownPhase::Killed);
             }
           },
-        // If we have an up-to-date version that's either starting or running then we won't launch anything.
-        (
-          Some(target_spec),
-          Some((
-            running_version,
-            RunningProcessEntry {
-              status: ProcessStatus::Starting | ProcessStatus::Running,
-              ..
-            },
-          )),
-        ) if *target_spec == running_version => {}
-        // Otherwise, launch a new version.
-        (Some(target_spec), _) => {
-          if check_rate_limit(&process_name, LAUNCH_RATE_LIMIT).is_success() {
-            rate_limit_event(&process_name, LAUNCH_RATE_LIMIT);
-            let process_entry =
-              match self.launch_process(free_loopback_ports, allocated_ports, target_spec) {
-                Ok(process_entry) => process_entry,
-                Err(e) => {
-                  log_event(LogEvent::Error {
-                    msg: format!("Failed to launch process {}: {}", process_name, e),
-                  });
-                  continue;
-                }
+        // Otherwise, make sure every replica has an up-to-date version that's starting or running.
+        Some(target_spec) => {
+          let mut filled_replicas = HashSet::new();
+          let mut have_outdated_version = false;
+          let mut have_starting_replica = false;
+          for (spec, entry) in &process_set.running_versions {
+            if !matches!(
+              entry.status,
+              ProcessStatus::Preparing | ProcessStatus::Starting | ProcessStatus::Running
+            ) {
+              continue;
+            }
+            if !spec.is_same_version(target_spec) {
+              have_outdated_version = true;
+              continue;
+            }
+            filled_replicas.insert(entry.replica);
+            have_starting_replica |= entry.status != ProcessStatus::Running;
+          }
+          for replica in 0..target_spec.replicas {
+            if filled_replicas.contains(&replica) {
+              continue;
+            }
+            // Don't launch anything until all of our dependencies are running.
+            if let Some(dependency) = target_spec
+              .depends_on
+              .iter()
+              .find(|dependency| !running_processes.contains(*dependency))
+            {
+              process_set.waiting_on = Some(dependency.clone());
+              break;
+            }
+            // When upgrading, roll over to the new version one replica at a time.
+            if have_outdated_version && have_starting_replica {
+              break;
+            }
+            // If this version has been launched into this slot before, then this is a relaunch,
+            // which goes by the restart policy and backoff.
+            let now = std::time::Instant::now();
+            let mut restarts = 0;
+            if let Some(previous) = process_set
+              .restarts
+              .replicas
+              .get_mut(&replica)
+              .filter(|previous| previous.spec.is_same_version(target_spec))
+            {
+              let should_restart = match (target_spec.restart, previous.succeeded) {
+                // Processes that are still around are getting replaced, which isn't up to policy.
+                (_, None) | (RestartPolicy::Always, _) => true,
+                (RestartPolicy::OnFailure, Some(succeeded)) => !succeeded,
+                (RestartPolicy::Never, Some(_)) => false,
               };
-            process_set.running_versions.push((ProcessSpec::clone(target_spec), process_entry));
+              if !should_restart {
+                continue;
+              }
+              let backoff = &target_spec.backoff;
+              if previous.retry_at.is_none() {
+                if previous.launched_at.elapsed() >= backoff.reset_after.0 {
+                  previous.restarts = 0;
+                }
+                let delay = backoff.delay(previous.restarts);
+                previous.retry_at = Some(now + delay);
+                log_event(LogEvent::RestartBackoff {
+                  process_name: process_name.clone(),
+                  replica,
+                  delay_ms: delay.as_millis() as u64,
+                });
+              }
+              if previous.retry_at.is_some_and(|retry_at| now < retry_at) {
+                continue;
+              }
+              restarts = previous.restarts + 1;
+              process_set.restarts.note_restart();
+            }
+            let launch =
+              self.launch_process(free_loopback_ports, allocated_ports, logs, target_spec, replica);
+            process_set.restarts.replicas.insert(replica, ReplicaState {
+              spec: ProcessSpec::clone(target_spec),
+              name: launch.as_ref().ok().map(|process_entry| process_entry.name.clone()),
+              launched_at: now,
+              restarts,
+              retry_at: None,
+              succeeded: None,
+            });
+            match launch {
+              Ok(process_entry) => {
+                process_set.running_versions.push((ProcessSpec::clone(target_spec), process_entry));
+                have_starting_replica = true;
+              }
+              Err(e) => log_event(LogEvent::Error {
+                msg: format!("Failed to launch process {}: {}", process_name, e),
+              }),
+            }
           }
         }
       };
@@ -646,17 +2746,11 @@ impl GlobalState {
     }
 
     // Update statuses on processes.
-    for process_set in processes_by_name.values_mut() {
-      macro_rules! update_status {
-        ($entry:ident, $status:expr) => {{
-          let status = $status;
-          log_event(LogEvent::StatusChange {
-            name: $entry.name.clone(),
-            status,
-          });
-          $entry.status = status;
-        }};
-      }
+    let mut probe_results = HashMap::<(String, Probe), Vec<Result<bool, Error>>>::new();
+    for (name, probe, result) in std::mem::take(&mut self.probe_results.lock().unwrap().finished) {
+      probe_results.entry((name, probe)).or_default().push(result);
+    }
+    for (process_name, process_set) in processes_by_name.iter_mut() {
       // Update connection counts.
       for (_, entry) in &mut process_set.running_versions {
         entry.approx_conn_count = entry
@@ -665,90 +2759,275 @@ impl GlobalState {
           .map(|port| loopback_info.get(port).map(|info| info.connections).unwrap_or(0))
           .sum();
       }
-      // Perform health checks on running processes.
+      // Spawn processes that have finished preparing.
       for (spec, entry) in &mut process_set.running_versions {
-        if entry.status == ProcessStatus::Running
-          && check_rate_limit(&entry.name, HEALTH_CHECK_RATE_LIMIT).is_success()
-        {
-          rate_limit_event(&entry.name, HEALTH_CHECK_RATE_LIMIT);
-          // FIXME: Blocking on this potentially slow health check is bad.
-          if !self.health_check(spec, entry).await? {
-            update_status!(entry, ProcessStatus::Unhealthy);
+        entry.finish_preparation(spec).await;
+      }
+      // Take in the results of finished health checks.
+      for (spec, entry) in &mut process_set.running_versions {
+        for &probe in PROBES {
+          for result in probe_results.remove(&(entry.name.clone(), probe)).unwrap_or_default() {
+            // A check that can't even run counts as failing.
+            let healthy = result.unwrap_or_else(|e| {
+              log_event(LogEvent::Error {
+                msg: format!("Failed to run {:?} probe of {}: {:#}", probe, entry.name, e),
+              });
+              false
+            });
+            entry.record_probe_result(spec, probe, healthy);
+          }
+        }
+        // Without a readiness probe, there's nothing to wait for.
+        if entry.status == ProcessStatus::Starting && Probe::Readiness.check(spec).is_none() {
+          entry.update_status(ProcessStatus::Running);
+        }
+        if entry.status == ProcessStatus::Running && !entry.ran_post_start {
+          entry.ran_post_start = true;
+          if let Some(command) = &spec.hooks.post_start {
+            entry.spawn_lifecycle_hook(spec, LifecycleHook::PostStart, command);
           }
         }
       }
-      // Perform start-up checks on starting processes.
-      if let Some((spec, entry)) = process_set.running_versions.last_mut() {
+      // Give up on processes that take too long to become ready. Their replacements are subject
+      // to the backoff, so a process that never starts doesn't get relaunched in a tight loop.
+      for (spec, entry) in &mut process_set.running_versions {
+        let Some(startup_timeout) = spec.startup_timeout else {
+          continue;
+        };
         if entry.status == ProcessStatus::Starting
-          && check_rate_limit(&entry.name, START_RATE_LIMIT).is_success()
+          && entry.approx_start.elapsed() >= startup_timeout.0
         {
-          rate_limit_event(&entry.name, START_RATE_LIMIT);
-          // FIXME: Blocking on this potentially slow health check is bad.
-          if self.health_check(spec, entry).await? {
-            update_status!(entry, ProcessStatus::Running);
+          let reason = StartFailure::Timeout {
+            after_secs: startup_timeout.0.as_secs(),
+          };
+          entry.mark_failed_to_start(reason);
+          process_set.restarts.note_finished(entry);
+        }
+      }
+      // Advance the graceful shutdown of sunsetting processes.
+      for (spec, entry) in &mut process_set.running_versions {
+        let Some(shutdown_phase) = &entry.shutdown_phase else {
+          continue;
+        };
+        if entry.is_exited() {
+          continue;
+        }
+        match shutdown_phase {
+          ShutdownPhase::Draining { since }
+            if entry.approx_conn_count <= 0 || since.elapsed() >= spec.shutdown.drain_timeout.0 =>
+            match &spec.hooks.pre_stop {
+              Some(command) => {
+                let hook = entry.spawn_lifecycle_hook(spec, LifecycleHook::PreStop, command);
+                entry.shutdown_phase = Some(ShutdownPhase::PreStop { hook });
+              }
+              None => entry.send_stop_signal(spec),
+            },
+          // A failed pre-stop hook has already been logged, and doesn't stop us shutting down.
+          ShutdownPhase::PreStop { hook } if hook.result.lock().unwrap().is_some() =>
+            entry.send_stop_signal(spec),
+          ShutdownPhase::Stopping { since } if since.elapsed() >= spec.shutdown.kill_after.0 => {
+            log_event(LogEvent::KillAfterTimeout {
+              name: entry.name.clone(),
+            });
+            if let Err(e) = entry.process.start_kill() {
+              log_event(LogEvent::Error {
+                msg: format!("Failed to send SIGKILL to {}: {}", entry.name, e),
+              });
+            }
+            entry.shutdown_phase = Some(ShutdownPhase::Killed);
           }
+          _ => {}
         }
       }
-      // If there's a newer running version, then sunset the older running versions.
-      let mut have_newer_running_version = false;
-      for i in (0..process_set.running_versions.len()).rev() {
-        let (_, entry) = &mut process_set.running_versions[i];
-        if matches!(
-          entry.status,
-          ProcessStatus::Starting | ProcessStatus::Running | ProcessStatus::Unhealthy
-        ) {
-          if have_newer_running_version {
-            update_status!(entry, ProcessStatus::Sunsetting);
-            // When doing so, send a SIGINT to the process.
-            match entry.process.id() {
-              Some(pid) => unsafe {
-                libc::kill(pid as i32, libc::SIGTERM);
+      // Sunset versions that are no longer needed. Outdated versions are only kept around while
+      // there aren't yet enough healthy up-to-date replicas to take over their traffic.
+      if let Some(target_spec) = specs.get(process_name.as_str()) {
+        let is_up_to_date = |spec: &ProcessSpec, entry: &RunningProcessEntry| {
+          spec.is_same_version(target_spec) && entry.replica < target_spec.replicas
+        };
+        let healthy_replicas = process_set
+          .running_versions
+          .iter()
+          .filter(|(spec, entry)| {
+            entry.status == ProcessStatus::Running && entry.ready && is_up_to_date(spec, entry)
+          })
+          .count();
+        let mut outdated_to_keep = target_spec.replicas.saturating_sub(healthy_replicas);
+        let mut replaced_replicas = HashSet::new();
+        for i in (0..process_set.running_versions.len()).rev() {
+          let (spec, entry) = &mut process_set.running_versions[i];
+          let should_sunset = match entry.status {
+            // There's no point in finishing the preparation of an outdated version.
+            ProcessStatus::Preparing => !is_up_to_date(spec, entry),
+            ProcessStatus::Starting | ProcessStatus::Running if is_up_to_date(spec, entry) => false,
+            ProcessStatus::Starting | ProcessStatus::Running =>
+              if outdated_to_keep > 0 {
+                outdated_to_keep -= 1;
+                false
+              } else {
+                true
               },
-              None => log_event(LogEvent::Error {
-                msg: format!("Failed to send SIGTERM to {}: no PID available", entry.name),
-              }),
-            }
+            // Unhealthy versions get sunset once they've been replaced.
+            ProcessStatus::Unhealthy =>
+              replaced_replicas.contains(&entry.replica) || entry.replica >= target_spec.replicas,
+            _ => continue,
+          };
+          if !should_sunset {
+            replaced_replicas.insert(entry.replica);
+            continue;
           }
-          have_newer_running_version = true;
+          entry.update_status(ProcessStatus::Sunsetting);
+          if let ProcessHandle::Preparing(preparation) = &entry.process {
+            preparation.abort();
+            continue;
+          }
+          // Move traffic away first, and only stop the process once its connections drain.
+          log_event(LogEvent::Draining {
+            name:             entry.name.clone(),
+            open_connections: entry.approx_conn_count,
+          });
+          entry.shutdown_phase = Some(ShutdownPhase::Draining {
+            since: std::time::Instant::now(),
+          });
         }
       }
       // If a process has exited, then set it to exited.
       for (_, entry) in &mut process_set.running_versions {
-        if matches!(entry.status, ProcessStatus::Exited { .. }) {
+        if entry.is_exited() {
+          continue;
+        }
+        if let Some(exit) = entry.process.try_wait()? {
+          entry.mark_exited(exit);
+          process_set.restarts.note_finished(entry);
+        }
+      }
+    }
+
+    // Trigger jobs that have changed, or whose schedule has come up.
+    let now_minute = get_unix_time() / 60;
+    for job_spec in &target.jobs {
+      let job_state = jobs_by_name.entry(job_spec.name.clone()).or_insert_with(JobState::new);
+      if job_state.seen_spec.as_ref() != Some(job_spec) {
+        if job_spec.runs_on_change() {
+          job_state.pending.get_or_insert(JobTrigger::Change);
+        }
+        job_state.seen_spec = Some(job_spec.clone());
+      }
+      if let Some(schedule) = &job_spec.schedule {
+        let schedule = cron::Schedule::parse(schedule)?;
+        // Check every minute since our last pass, but don't try to catch up more than a day.
+        let first_minute =
+          (job_state.last_checked_minute + 1).max(now_minute.saturating_sub(24 * 60));
+        if schedule
+          .first_fire(first_minute..=now_minute, |minute| {
+            cron::TimeFields::from_local_time(minute * 60)
+          })
+          .is_some()
+        {
+          job_state.pending.get_or_insert(JobTrigger::Schedule);
+        }
+      }
+      job_state.last_checked_minute = now_minute;
+    }
+
+    // Do upkeep on job runs, and start pending runs once the previous one has finished.
+    for (job_name, job_state) in jobs_by_name.iter_mut() {
+      let job_spec = target.jobs.iter().find(|job_spec| job_spec.name == *job_name);
+      for run in &mut job_state.runs {
+        let entry = &mut run.entry;
+        if entry.is_exited() {
           continue;
         }
-        if let Some(exit_status) = entry.process.try_wait()? {
-          update_status!(entry, ProcessStatus::Exited {
-            exit_status: exit_status.code().unwrap_or(-1),
-            approx_time: get_unix_time(),
+        entry.finish_preparation(&run.spec).await;
+        // Jobs have no health checks, so they count as running as soon as they've started.
+        if entry.status == ProcessStatus::Starting {
+          entry.update_status(ProcessStatus::Running);
+        }
+        if job_spec.is_none() && !entry.is_exited() {
+          log_event(LogEvent::Kill {
+            name: entry.name.clone(),
+          });
+          entry.process.start_kill().ok();
+          entry.shutdown_phase = Some(ShutdownPhase::Killed);
+        }
+        if let Some(exit) = entry.process.try_wait()? {
+          entry.mark_exited(exit);
+        }
+        if let ProcessStatus::Exited { exit_status, .. } = entry.status {
+          log_event(LogEvent::JobFinished {
+            name: entry.name.clone(),
+            job_name: job_name.clone(),
+            exit_status,
           });
         }
       }
+      let Some(job_spec) = job_spec else {
+        job_state.pending = None;
+        continue;
+      };
+      if job_state.is_running() {
+        continue;
+      }
+      let Some(trigger) = job_state.pending.take() else {
+        continue;
+      };
+      log_event(LogEvent::TriggerJob {
+        job_name: job_name.clone(),
+        trigger,
+      });
+      let spec = job_spec.to_process_spec();
+      match self.launch_process(free_loopback_ports, allocated_ports, logs, &spec, 0) {
+        Ok(entry) => job_state.runs.push(JobRun {
+          trigger,
+          spec,
+          entry,
+        }),
+        Err(e) => log_event(LogEvent::Error {
+          msg: format!("Failed to launch job {}: {}", job_name, e),
+        }),
+      }
     }
 
-    // Release ports of exited processes.
+    // Release ports of exited processes, including those that failed to start, and remove them
+    // from their IPVS services.
     for process_set in processes_by_name.values_mut() {
       for (_, entry) in &mut process_set.running_versions {
-        if matches!(entry.status, ProcessStatus::Exited { .. }) {
-          for port in std::mem::take(&mut entry.port_allocations).values() {
-            release_port(free_loopback_ports, allocated_ports, *port);
+        if entry.is_exited() {
+          for (service_name, port) in std::mem::take(&mut entry.port_allocations) {
+            if let Some(service) = target.services.iter().find(|s| s.name == service_name) {
+              if let Err(e) = ipvs::set_loopback_weight(service, port, 0) {
+                log_event(LogEvent::Warning {
+                  msg: format!("Failed to remove port {} from {}: {}", port, service_name, e),
+                });
+              }
+            }
+            release_port(free_loopback_ports, allocated_ports, port);
           }
         }
       }
     }
 
-    // // Drop entries that have exited.
-    // for process_set in processes_by_name.values_mut() {
-    //   process_set
-    //     .running_versions
-    //     .retain(|(_, entry)| !matches!(entry.status, ProcessStatus::Exited { .. }));
-    // }
+    // Drop exited entries that are past their retention, and processes and jobs that are gone for
+    // good. Processes still in the target keep their sets even when empty, as those hold their
+    // restart history and what they're waiting on.
+    for description in Self::collect_garbage(processes_by_name, jobs_by_name, retention, false) {
+      log_event(LogEvent::GarbageCollect { description });
+    }
+    processes_by_name.retain(|process_name, process_set| {
+      !process_set.running_versions.is_empty()
+        || target.processes.iter().any(|process_spec| process_spec.name == *process_name)
+    });
+    jobs_by_name.retain(|job_name, job_state| {
+      !job_state.runs.is_empty() || target.jobs.iter().any(|job_spec| job_spec.name == *job_name)
+    });
+    // Record any processes launched since we started, before anything below can fail.
+    Self::persist_processes(processes_by_name, jobs_by_name, persisted_processes);
 
     // Adjust IPVS weights based on health of process sets.
     for process_set in processes_by_name.values() {
       for (_, entry) in &process_set.running_versions {
         let target_weight = match entry.status {
-          ProcessStatus::Running => 1,
+          ProcessStatus::Running if entry.ready => 1,
           _ => 0,
         };
         for (service_name, port) in &entry.port_allocations {
@@ -765,7 +3044,12 @@ impl GlobalState {
               port:    *port,
               weight:  target_weight,
             });
-            ipvs::set_loopback_weight(service, *port, target_weight)?;
+            // Sunsetting processes keep their real server at weight zero while they drain, so
+            // that their remaining connections stay visible.
+            match entry.status {
+              ProcessStatus::Sunsetting => ipvs::drain_loopback(service, *port)?,
+              _ => ipvs::set_loopback_weight(service, *port, target_weight)?,
+            }
           }
         }
       }
@@ -774,24 +3058,383 @@ impl GlobalState {
     Ok(())
   }
 
+  /// Deals with processes left running by a previous launcho server. Depending on the policy, we
+  /// either adopt the ones that match the current target, or terminate them.
+  async fn recover_orphans(&self, policy: OrphanPolicy) -> Result<(), Error> {
+    let records: Vec<PersistedProcess> = match std::fs::read_to_string(get_processes_path()?) {
+      Ok(text) => serde_json::from_str(&text)?,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+      Err(e) => return Err(e.into()),
+    };
+    let mut to_terminate = Vec::new();
+    let mut dead = Vec::new();
+    let mut synced = self.synced.lock().await;
+    let SyncedGlobalState {
+      target,
+      processes_by_name,
+      free_loopback_ports,
+      allocated_ports,
+      logs,
+      ..
+    } = &mut *synced;
+    let mut adopted_replicas = HashSet::new();
+    for record in records {
+      if !is_pid_alive(record.pid, record.start_time) {
+        dead.push(record);
+        continue;
+      }
+      // We can only adopt processes for which we still have a spec, at the same version.
+      let spec = target.processes.iter().find(|spec| spec.name == record.process_name);
+      let adoptable = policy == OrphanPolicy::Adopt
+        && spec.is_some_and(|spec| {
+          spec_hash(spec) == record.spec_hash && record.replica < spec.replicas
+        })
+        && !adopted_replicas.contains(&(record.process_name.clone(), record.replica))
+        && record
+          .port_allocations
+          .values()
+          .all(|port| !allocated_ports.contains(port) && free_loopback_ports.contains(port));
+      if !adoptable {
+        to_terminate.push(record);
+        continue;
+      }
+      for port in record.port_allocations.values() {
+        free_loopback_ports.retain(|p| p != port);
+        allocated_ports.insert(*port);
+      }
+      log_event(LogEvent::AdoptProcess {
+        name:         record.name.clone(),
+        process_name: record.process_name.clone(),
+        pid:          record.pid,
+      });
+      adopted_replicas.insert((record.process_name.clone(), record.replica));
+      let spec = ProcessSpec::clone(spec.unwrap());
+      let process_set =
+        processes_by_name.entry(record.process_name.clone()).or_insert_with(ProcessSet::new);
+      let mut entry = RunningProcessEntry::adopt(record, logs);
+      // The process already told the last server it was ready, and won't say so again.
+      if uses_notify(&spec) {
+        match NotifySocket::bind(&entry.name, &spec, true) {
+          Ok(notify) => entry.notify = Some(notify),
+          Err(e) => log_event(LogEvent::Warning {
+            msg: format!("{:#}", e),
+          }),
+        }
+      }
+      process_set.running_versions.push((spec, entry));
+    }
+    std::mem::drop(synced);
+    for record in &dead {
+      record.remove_leftovers().await;
+    }
+
+    // Terminate everything else: SIGTERM, then SIGKILL whatever is left after a timeout.
+    for record in &to_terminate {
+      log_event(LogEvent::TerminateOrphan {
+        name: record.name.clone(),
+        pid:  record.pid,
+      });
+      unsafe { libc::kill(record.pid as i32, libc::SIGTERM) };
+    }
+    let deadline = std::time::Instant::now() + ORPHAN_KILL_TIMEOUT;
+    while std::time::Instant::now() < deadline
+      && to_terminate.iter().any(|record| is_pid_alive(record.pid, record.start_time))
+    {
+      tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    for record in &to_terminate {
+      if is_pid_alive(record.pid, record.start_time) {
+        log_event(LogEvent::KillAfterTimeout {
+          name: record.name.clone(),
+        });
+        unsafe { libc::kill(record.pid as i32, libc::SIGKILL) };
+      }
+      record.remove_leftovers().await;
+    }
+    Ok(())
+  }
+
+  /// Drops exited entries that fall outside of the retention policy, along with their spooled
+  /// output, temporary working directories and cgroups. Returns a description of each dropped
+  /// entry.
+  fn collect_garbage(
+    processes_by_name: &mut HashMap<String, ProcessSet>,
+    jobs_by_name: &mut HashMap<String, JobState>,
+    retention: &RetentionSpec,
+    dry_run: bool,
+  ) -> Vec<String> {
+    let mut descriptions = Vec::new();
+    for (process_name, process_set) in processes_by_name.iter_mut() {
+      Self::collect_exited_entries(
+        process_name,
+        &mut process_set.running_versions,
+        |(_, entry)| entry,
+        retention,
+        dry_run,
+        &mut descriptions,
+      );
+    }
+    for (job_name, job_state) in jobs_by_name.iter_mut() {
+      Self::collect_exited_entries(
+        job_name,
+        &mut job_state.runs,
+        |run| &run.entry,
+        retention,
+        dry_run,
+        &mut descriptions,
+      );
+    }
+    descriptions
+  }
+
+  fn collect_exited_entries<T>(
+    name: &str,
+    items: &mut Vec<T>,
+    get_entry: impl Fn(&T) -> &RunningProcessEntry,
+    retention: &RetentionSpec,
+    dry_run: bool,
+    descriptions: &mut Vec<String>,
+  ) {
+    let now = get_unix_time();
+    let mut keep = vec![true; items.len()];
+    let mut exited_count = 0;
+    for (i, item) in items.iter().enumerate().rev() {
+      let entry = get_entry(item);
+      let Some(approx_time) = entry.status.finished_time() else {
+        continue;
+      };
+      exited_count += 1;
+      let age = now.saturating_sub(approx_time);
+      if exited_count <= retention.keep_exited && age <= retention.max_age.0.as_secs() {
+        continue;
+      }
+      keep[i] = false;
+      let mut description = format!(
+        "{}: {} (exited {:.0?} ago)",
+        name,
+        entry.name,
+        std::time::Duration::from_secs(age)
+      );
+      if entry.temp_cwd {
+        description.push_str(&format!(", removing {:?}", entry.cwd));
+      }
+      descriptions.push(description);
+    }
+    if dry_run {
+      return;
+    }
+    let mut keep = keep.into_iter();
+    items.retain(|item| {
+      let keep = keep.next().unwrap();
+      if !keep {
+        get_entry(item).remove_leftovers();
+      }
+      keep
+    });
+  }
+
+  /// Streams a process's output, starting with what it has so far. Once a newer version of the
+  /// same replica has started, we move on to following that.
+  async fn follow_logs(
+    &'static self,
+    name: &str,
+    mut options: LogOptions,
+  ) -> Result<tokio::sync::mpsc::Receiver<bytes::Bytes>, String> {
+    let (process_name, replica, mut name, mut output) = {
+      let mut synced = self.synced.lock().await;
+      let entries = Self::find_log_entries(&mut synced, name, LogVersions::Current, false)?;
+      let entry = entries[0];
+      (entry.process_name.clone(), entry.replica, entry.name.clone(), entry.output.clone())
+    };
+    let (sender, receiver) = tokio::sync::mpsc::channel(16);
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(FOLLOW_CHECK_INTERVAL);
+      loop {
+        let Ok((output_so_far, mut updates)) = output.follow(&options).await else {
+          return;
+        };
+        if sender.send(output_so_far.into()).await.is_err() {
+          return;
+        }
+        let newer = loop {
+          let chunk = tokio::select! {
+            update = updates.recv() => match update {
+              Ok(line) if options.stderr_only && line.stream != OutputStream::Stderr => continue,
+              Ok(line) => line.format(&options).into(),
+              Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) =>
+                bytes::Bytes::from_static(b"(launcho: skipped lines that came in too fast)\n"),
+              // We hold on to the output, so this can't happen.
+              Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+            },
+            _ = ticker.tick() => {
+              if sender.is_closed() {
+                return;
+              }
+              match self.newest_version(&process_name, replica).await {
+                Some((newest_name, newest_output)) if newest_name != name =>
+                  break (newest_name, newest_output),
+                _ => continue,
+              }
+            }
+          };
+          if sender.send(chunk).await.is_err() {
+            return;
+          }
+        };
+        (name, output) = newer;
+        // A newer version's output is all new to the follower.
+        options.since_secs = None;
+        options.tail = None;
+        let note = format!("--- launcho: now following {} ---\n", name);
+        if sender.send(note.into()).await.is_err() {
+          return;
+        }
+      }
+    });
+    Ok(receiver)
+  }
+
+  /// Finds the newest version of a replica of a process that has been spawned.
+  async fn newest_version(
+    &self,
+    process_name: &str,
+    replica: usize,
+  ) -> Option<(String, Arc<SpooledOutput>)> {
+    let synced = self.synced.lock().await;
+    let process_set = synced.processes_by_name.get(process_name)?;
+    process_set
+      .running_versions
+      .iter()
+      .rev()
+      .map(|(_, entry)| entry)
+      .find(|entry| entry.replica == replica && entry.status != ProcessStatus::Preparing)
+      .map(|entry| (entry.name.clone(), entry.output.clone()))
+  }
+
   fn find_matching_process<'a>(
     name: &str,
-    processes_by_name: &'a mut HashMap<String, ProcessSet>,
+    entries: impl Iterator<Item = &'a mut RunningProcessEntry>,
   ) -> Result<&'a mut RunningProcessEntry, String> {
     let mut result = Err(format!("no process matching {:?} found", name));
-    for process_set in processes_by_name.values_mut() {
-      for (_, entry) in &mut process_set.running_versions {
-        if entry.name.starts_with(&name) {
-          if result.is_ok() {
-            return Err(format!("multiple processes matching {:?} found", name));
-          }
-          result = Ok(entry);
+    for entry in entries {
+      if entry.name.starts_with(name) {
+        if result.is_ok() {
+          return Err(format!("multiple processes matching {:?} found", name));
         }
+        result = Ok(entry);
       }
     }
     result
   }
 
+  /// Finds which entries to get logs from. The name of a process or job picks from its versions
+  /// (or runs), oldest first, and otherwise we match the name of a single version by prefix.
+  fn find_log_entries<'a>(
+    synced: &'a mut SyncedGlobalState,
+    name: &str,
+    versions: LogVersions,
+    hook: bool,
+  ) -> Result<Vec<&'a RunningProcessEntry>, String> {
+    let entries: Vec<&RunningProcessEntry> = if synced.processes_by_name.contains_key(name) {
+      synced.processes_by_name[name].running_versions.iter().map(|(_, entry)| entry).collect()
+    } else if synced.jobs_by_name.contains_key(name) {
+      synced.jobs_by_name[name].runs.iter().map(|run| &run.entry).collect()
+    } else {
+      return match versions {
+        LogVersions::Current =>
+          Self::find_matching_process(name, synced.all_entries_mut()).map(|entry| vec![&*entry]),
+        _ => Err(format!("no process or job named {:?} found", name)),
+      };
+    };
+    // Versions that are still preparing only have the output of their before steps so far.
+    let entries: Vec<_> = entries
+      .into_iter()
+      .filter(|entry| hook || entry.status != ProcessStatus::Preparing)
+      .collect();
+    Self::pick_versions(entries, |entry| entry.replica, name, versions)
+  }
+
+  /// Like `find_log_entries` (without hooks), but also finds versions whose entries are gone, from
+  /// the log directories they left behind.
+  fn find_log_sources(
+    synced: &mut SyncedGlobalState,
+    log_dirs: &[LogDir],
+    name: &str,
+    versions: LogVersions,
+  ) -> Result<Vec<LogSource>, String> {
+    let live_names: HashSet<String> =
+      synced.all_entries_mut().map(|entry| entry.name.clone()).collect();
+    let gone_dirs: Vec<&LogDir> =
+      log_dirs.iter().filter(|log_dir| !live_names.contains(&log_dir.name)).collect();
+    let is_of_process =
+      |log_dir: &&LogDir| log_dir.info.as_ref().is_some_and(|info| info.process_name == name);
+    let entries: Vec<&RunningProcessEntry> = if synced.processes_by_name.contains_key(name) {
+      synced.processes_by_name[name].running_versions.iter().map(|(_, entry)| entry).collect()
+    } else if synced.jobs_by_name.contains_key(name) {
+      synced.jobs_by_name[name].runs.iter().map(|run| &run.entry).collect()
+    } else if gone_dirs.iter().any(is_of_process) {
+      Vec::new()
+    } else {
+      if versions != LogVersions::Current {
+        return Err(format!("no process or job named {:?} found", name));
+      }
+      let matching: Vec<LogSource> = gone_dirs
+        .iter()
+        .filter(|log_dir| log_dir.name.starts_with(name))
+        .map(|log_dir| LogSource::from_log_dir(log_dir))
+        .chain(
+          synced
+            .all_entries_mut()
+            .filter(|entry| entry.name.starts_with(name))
+            .map(|entry| LogSource::from_entry(entry)),
+        )
+        .collect();
+      return match matching.len() {
+        0 => Err(format!("no process matching {:?} found", name)),
+        1 => Ok(matching),
+        _ => Err(format!("multiple processes matching {:?} found", name)),
+      };
+    };
+    // Gone versions came before any we still have entries for.
+    let sources: Vec<LogSource> = gone_dirs
+      .into_iter()
+      .filter(is_of_process)
+      .map(LogSource::from_log_dir)
+      .chain(
+        entries
+          .into_iter()
+          .filter(|entry| entry.status != ProcessStatus::Preparing)
+          .map(LogSource::from_entry),
+      )
+      .collect();
+    Self::pick_versions(sources, |source| source.replica, name, versions)
+  }
+
+  /// Picks from a process's versions, given oldest first.
+  fn pick_versions<T>(
+    mut candidates: Vec<T>,
+    replica: impl Fn(&T) -> usize,
+    name: &str,
+    versions: LogVersions,
+  ) -> Result<Vec<T>, String> {
+    let Some(current) = candidates.pop() else {
+      return Err(format!("{} has no versions with output yet", name));
+    };
+    match versions {
+      LogVersions::Current => Ok(vec![current]),
+      LogVersions::Previous => candidates
+        .into_iter()
+        .rev()
+        .find(|candidate| replica(candidate) == replica(&current))
+        .map(|candidate| vec![candidate])
+        .ok_or_else(|| format!("{} has no previous version", name)),
+      LogVersions::All => {
+        candidates.push(current);
+        Ok(candidates)
+      }
+    }
+  }
+
   fn validate_target(target: &LaunchoTarget) -> Result<(), Error> {
     // Make sure all process and service names are unique.
     macro_rules! check_unique {
@@ -806,6 +3449,136 @@ impl GlobalState {
     }
     check_unique!("processes", target.processes);
     check_unique!("services", target.services);
+    check_unique!("jobs", target.jobs);
+    // Jobs are looked up by name alongside processes, like for logs.
+    for job in &target.jobs {
+      if target.processes.iter().any(|process| process.name == job.name) {
+        bail!("Job {} has the same name as a process", job.name);
+      }
+    }
+    // Make sure there's something to run.
+    for process in &target.processes {
+      if process.command.is_empty() {
+        bail!("Process {} has an empty command", process.name);
+      }
+    }
+    for job in &target.jobs {
+      if job.command.is_empty() {
+        bail!("Job {} has an empty command", job.name);
+      }
+    }
+    // Make sure job schedules parse.
+    for job in &target.jobs {
+      if let Some(Err(e)) = job.schedule.as_deref().map(cron::Schedule::parse) {
+        bail!("Job {}: {:#}", job.name, e);
+      }
+    }
+    // Make sure dependencies exist, and have no cycles.
+    let specs: HashMap<&str, &ProcessSpec> =
+      target.processes.iter().map(|spec| (spec.name.as_str(), spec)).collect();
+    for spec in &target.processes {
+      for dependency in &spec.depends_on {
+        if !specs.contains_key(dependency.as_str()) {
+          bail!("Process {} depends on unknown process {}", spec.name, dependency);
+        }
+      }
+    }
+    fn check_cycles<'a>(
+      name: &'a str,
+      specs: &HashMap<&str, &'a ProcessSpec>,
+      path: &mut Vec<&'a str>,
+      done: &mut HashSet<&'a str>,
+    ) -> Result<(), Error> {
+      if let Some(i) = path.iter().position(|n| *n == name) {
+        bail!("Dependency cycle: {} -> {}", path[i..].join(" -> "), name);
+      }
+      if !done.insert(name) {
+        return Ok(());
+      }
+      path.push(name);
+      for dependency in &specs[name].depends_on {
+        check_cycles(dependency, specs, path, done)?;
+      }
+      path.pop();
+      Ok(())
+    }
+    let mut done = HashSet::new();
+    for spec in &target.processes {
+      check_cycles(&spec.name, &specs, &mut Vec::new(), &mut done)?;
+    }
+    // Make sure limits are in range.
+    for (name, limits) in target
+      .processes
+      .iter()
+      .map(|spec| (&spec.name, &spec.limits))
+      .chain(target.jobs.iter().map(|spec| (&spec.name, &spec.limits)))
+    {
+      if limits.io_weight.is_some_and(|io_weight| !(1..=10000).contains(&io_weight)) {
+        bail!("{}: io_weight must be between 1 and 10000", name);
+      }
+      if limits.cpu.is_some_and(|cpu| cpu.millicpus < cgroup::MIN_MILLICPUS) {
+        bail!("{}: cpu limit must be at least {}m", name, cgroup::MIN_MILLICPUS);
+      }
+    }
+    // Make sure backoffs grow, rather than shrink.
+    for process in &target.processes {
+      let multiplier = process.backoff.multiplier;
+      if multiplier.is_nan() || multiplier.is_infinite() || multiplier < 1.0 {
+        bail!("Process {}: backoff multiplier must be at least 1", process.name);
+      }
+    }
+    // Make sure all stop signals are ones we know how to send.
+    for process in &target.processes {
+      parse_signal(&process.shutdown.stop_signal)
+        .with_context(|| format!("Invalid stop_signal for process {}", process.name))?;
+    }
+    // Make sure health checks are ones we can run.
+    for process in &target.processes {
+      if process.health.is_some() && (process.readiness.is_some() || process.liveness.is_some()) {
+        bail!("Process {} can't have both health and readiness or liveness checks", process.name);
+      }
+      let checks = [
+        ("health", &process.health),
+        ("readiness", &process.readiness),
+        ("liveness", &process.liveness),
+      ];
+      for (which, check) in checks {
+        let Some(check) = check else {
+          continue;
+        };
+        if let Some(service) = check.kind.service() {
+          if !process.receives.iter().any(|receive| receive == service) {
+            bail!(
+              "Process {} {} checks service {}, which it doesn't receive",
+              process.name,
+              which,
+              service
+            );
+          }
+        }
+        if check.success_threshold == 0 || check.failure_threshold == 0 {
+          bail!("Process {} {} check thresholds must be at least 1", process.name, which);
+        }
+        match &check.kind {
+          HealthCheckKind::Exec { command } if command.is_empty() =>
+            bail!("Process {} has an empty {} check command", process.name, which),
+          // A health check is both, but READY=1 and WATCHDOG=1 mean different things.
+          HealthCheckKind::Notify {} if which == "health" => bail!(
+            "Process {} can't have a notify health check; use readiness and liveness instead",
+            process.name
+          ),
+          HealthCheckKind::Http(http) | HealthCheckKind::Https(http) => {
+            reqwest::Method::from_bytes(http.method.as_bytes()).with_context(|| {
+              format!(
+                "Invalid {} check method {:?} for process {}",
+                which, http.method, process.name
+              )
+            })?;
+          }
+          _ => {}
+        }
+      }
+    }
     // Make sure service ports and IPs are valid, and each service is on a unique IP+port pair.
     let mut services_on = HashSet::new();
     for service in &target.services {
@@ -843,7 +3616,7 @@ impl GlobalState {
       });
     }
     synced.clean_services.retain(|applied_service| {
-      if new_services.contains(&applied_service) {
+      if new_services.contains(applied_service) {
         true
       } else {
         // NB: This is a little weird to reconstitute.
@@ -862,8 +3635,10 @@ impl GlobalState {
       }
     });
 
-    // Clear all launch-related rate limits.
-    clear_launch_rate_limits();
+    // Let everything relaunch right away under the new target.
+    for process_set in synced.processes_by_name.values_mut() {
+      process_set.restarts.clear_backoff();
+    }
 
     synced.target_text = new_target_text;
     synced.target = new_target;
@@ -963,55 +3738,155 @@ impl GlobalState {
         let synced = self.synced.lock().await;
         let mut formatted_status = String::new();
         for (process_name, process_set) in &synced.processes_by_name {
-          formatted_status.push_str(&format!("{}:\n", process_name));
+          formatted_status.push_str(&format!("{}:", process_name));
+          if let Some(dependency) = &process_set.waiting_on {
+            formatted_status.push_str(&format!(" (waiting on {} to be running)", dependency));
+          }
+          let restarts = process_set.restarts.restarts_in_window();
+          if restarts > 0 {
+            formatted_status.push_str(&format!(" (restarts in the last hour: {})", restarts));
+          }
+          formatted_status.push('\n');
+          let restart = synced
+            .target
+            .processes
+            .iter()
+            .find(|spec| spec.name == *process_name)
+            .map(|spec| spec.restart)
+            .unwrap_or_default();
+          for description in process_set.restarts.describe_replicas(restart) {
+            formatted_status.push_str(&format!("  {}\n", description));
+          }
+          if !process_set.restarts.recent_exits.is_empty() {
+            let exits: Vec<String> =
+              process_set.restarts.recent_exits.iter().map(|code| code.to_string()).collect();
+            formatted_status.push_str(&format!("  recent exit statuses: {}\n", exits.join(", ")));
+          }
           for (_, entry) in &process_set.running_versions {
-            let duration = match entry.status {
-              ProcessStatus::Exited { approx_time, .. } =>
-                std::time::Duration::from_secs(get_unix_time() - approx_time),
-              _ => entry.approx_start.elapsed(),
+            let status = match entry.status {
+              ProcessStatus::FailedToStart { reason, .. } => format!("FailedToStart ({})", reason),
+              status => format!("{:?}", status),
             };
             formatted_status.push_str(&format!(
-              "  {}: {:?} (run-time: {:.0?})",
-              entry.name, entry.status, duration,
+              "  {} (replica {}): {} (run-time: {:.0?})",
+              entry.name,
+              entry.replica,
+              status,
+              entry.run_time(),
             ));
-            // FIXME: This bit doesn't even make sense, as a process
-            // can't be starting if it's failing to launch.
-            // TODO: Figure out what I want here.
-            // if entry.status == ProcessStatus::Starting
-            //   && check_rate_limit(&process_name, LAUNCH_RATE_LIMIT)
-            //     == RateLimitResult::Backoff
-            // {
-            //   formatted_status.push_str(" (too many crashes -- backing off)");
-            // }
-            formatted_status.push_str("\n");
+            if entry.status == ProcessStatus::Running && !entry.ready {
+              formatted_status.push_str(" (not ready, so getting no traffic)");
+            }
+            if let Some(status) = entry.notify.as_ref().and_then(NotifySocket::status) {
+              formatted_status.push_str(&format!(" (status: {})", status));
+            }
+            formatted_status.push('\n');
             if !entry.port_allocations.is_empty() {
               formatted_status.push_str("    ports:");
               for (service_name, port) in &entry.port_allocations {
                 formatted_status.push_str(&format!(" {}:{}", service_name, port));
               }
-              formatted_status.push_str("\n");
+              formatted_status.push('\n');
             }
           }
         }
+        for (job_name, job_state) in &synced.jobs_by_name {
+          formatted_status.push_str(&format!("job {}:", job_name));
+          if let Some(schedule) =
+            job_state.seen_spec.as_ref().and_then(|spec| spec.schedule.as_ref())
+          {
+            formatted_status.push_str(&format!(" (schedule: {})", schedule));
+          }
+          if let Some(trigger) = job_state.pending {
+            formatted_status.push_str(&format!(" (pending run: {:?})", trigger));
+          }
+          formatted_status.push('\n');
+          for run in &job_state.runs {
+            let result = match run.entry.status {
+              ProcessStatus::Exited { exit_status: 0, .. } => " succeeded",
+              ProcessStatus::Exited { .. } => " FAILED",
+              _ => "",
+            };
+            formatted_status.push_str(&format!(
+              "  {} ({:?}): {:?}{} (run-time: {:.0?})\n",
+              run.entry.name,
+              run.trigger,
+              run.entry.status,
+              result,
+              run.entry.run_time(),
+            ));
+          }
+        }
         ClientResponse::Status {
           events:     get_entire_log(),
           status:     formatted_status,
           ipvs_state: synced.last_ipvs_state.clone(),
         }
       }
-      ClientRequest::GetLogs { name } => {
+      ClientRequest::GetLogs {
+        name,
+        hook,
+        options,
+        versions,
+      } if hook => {
         let mut synced = self.synced.lock().await;
-        match Self::find_matching_process(&name, &mut synced.processes_by_name) {
-          Ok(entry) => ClientResponse::Logs {
-            name:   entry.name.clone(),
-            output: entry.output.get(),
-          },
-          Err(message) => ClientResponse::Error { message },
+        let entries = match Self::find_log_entries(&mut synced, &name, versions, hook) {
+          Ok(entries) => entries,
+          Err(message) => return Ok(ClientResponse::Error { message }),
+        };
+        let name = match versions {
+          LogVersions::All => name,
+          _ => entries[0].name.clone(),
+        };
+        // Hook output is only kept in memory, so this doesn't touch the disk.
+        let mut output = String::new();
+        for entry in &entries {
+          for step in &entry.hook_steps {
+            let result = step.result.lock().unwrap().clone();
+            let label = match versions {
+              LogVersions::All => format!("{}: {}", entry.name, step.label),
+              _ => step.label.clone(),
+            };
+            output.push_str(&format!(
+              "--- {} ({}) ---\n{}\n",
+              label,
+              result.as_deref().unwrap_or("not finished"),
+              step.command.trim(),
+            ));
+            output.push_str(&step.output.get(&options));
+          }
         }
+        ClientResponse::Logs { name, output }
+      }
+      ClientRequest::GetLogs {
+        name,
+        options,
+        versions,
+        ..
+      } => {
+        // Reading logs can take a while, so we do it without holding the lock, and off the runtime.
+        let log_dirs = find_log_dirs().await?;
+        let sources = {
+          let mut synced = self.synced.lock().await;
+          match Self::find_log_sources(&mut synced, &log_dirs, &name, versions) {
+            Ok(sources) => sources,
+            Err(message) => return Ok(ClientResponse::Error { message }),
+          }
+        };
+        let name = match versions {
+          LogVersions::All => name,
+          _ => sources[0].name.clone(),
+        };
+        let output = tokio::task::spawn_blocking(move || match versions {
+          LogVersions::All => combine_outputs(&sources, &options),
+          _ => format_lines(sources[0].output.lines().iter(), &options),
+        })
+        .await?;
+        ClientResponse::Logs { name, output }
       }
       ClientRequest::Restart { name } => {
         let mut synced = self.synced.lock().await;
-        match Self::find_matching_process(&name, &mut synced.processes_by_name) {
+        match Self::find_matching_process(&name, synced.process_entries_mut()) {
           Ok(entry) => match entry.status {
             ProcessStatus::Starting | ProcessStatus::Running => {
               log_event(LogEvent::ForceRestart {
@@ -1043,10 +3918,118 @@ impl GlobalState {
       ClientRequest::ListResources => ClientResponse::ResourceList {
         resources: storage::list_resources()?,
       },
+      ClientRequest::GarbageCollect { dry_run } => {
+        let mut synced = self.synced.lock().await;
+        let SyncedGlobalState {
+          processes_by_name,
+          jobs_by_name,
+          retention,
+          ..
+        } = &mut *synced;
+        let descriptions =
+          Self::collect_garbage(processes_by_name, jobs_by_name, retention, dry_run);
+        if !dry_run {
+          for description in &descriptions {
+            log_event(LogEvent::GarbageCollect {
+              description: description.clone(),
+            });
+          }
+        }
+        let mut message = match (dry_run, descriptions.is_empty()) {
+          (_, true) => "Nothing to remove\n".to_string(),
+          (true, false) => "Would remove:\n".to_string(),
+          (false, false) => "Removed:\n".to_string(),
+        };
+        for description in descriptions {
+          message.push_str(&format!("  {}\n", description));
+        }
+        ClientResponse::Success {
+          message: Some(message),
+        }
+      }
+      ClientRequest::RunJob { name } => {
+        let mut synced = self.synced.lock().await;
+        if !synced.target.jobs.iter().any(|job_spec| job_spec.name == name) {
+          return Ok(ClientResponse::Error {
+            message: format!("no job named {:?}", name),
+          });
+        }
+        let job_state = synced.jobs_by_name.entry(name.clone()).or_insert_with(JobState::new);
+        let message = match job_state.is_running() {
+          true => format!("Job {} is already running, it will run again after this run", name),
+          false => format!("Job {} will run shortly", name),
+        };
+        job_state.pending = Some(JobTrigger::Manual);
+        ClientResponse::Success {
+          message: Some(message),
+        }
+      }
       ClientRequest::ClearLaunchRateLimits => {
-        clear_launch_rate_limits();
+        let mut synced = self.synced.lock().await;
+        for process_set in synced.processes_by_name.values_mut() {
+          process_set.restarts.clear_backoff();
+        }
         ClientResponse::Success { message: None }
       }
+      ClientRequest::GetCrashReports { process } => ClientResponse::CrashReports {
+        reports: get_crash_reports(process.as_deref()),
+      },
+      ClientRequest::SearchLogs {
+        pattern,
+        processes,
+        since_secs,
+      } => {
+        let pattern = match regex::Regex::new(&pattern) {
+          Ok(pattern) => pattern,
+          Err(e) =>
+            return Ok(ClientResponse::Error {
+              message: format!("Invalid pattern: {}", e),
+            }),
+        };
+        let log_dirs = find_log_dirs().await?;
+        let mut outputs = Vec::new();
+        let mut live_names = HashSet::new();
+        {
+          let mut synced = self.synced.lock().await;
+          for entry in synced.all_entries_mut() {
+            live_names.insert(entry.name.clone());
+            let output = OutputSource::Spool(entry.output.clone());
+            outputs.push((entry.process_name.clone(), entry.name.clone(), output));
+            for step in &entry.hook_steps {
+              let name = format!("{} ({})", entry.name, step.label);
+              let output = OutputSource::Spool(step.output.clone());
+              outputs.push((entry.process_name.clone(), name, output));
+            }
+          }
+        }
+        // Versions we no longer have entries for may still have logs on disk.
+        for log_dir in log_dirs {
+          if !live_names.contains(&log_dir.name) {
+            let process_name = log_dir.info.map_or("?".to_string(), |info| info.process_name);
+            outputs.push((process_name, log_dir.name, OutputSource::LogDir(log_dir.path)));
+          }
+        }
+        // Processes can be given by spec name or by (a prefix of) a version's name.
+        let is_selected = |(process_name, name, _): &(String, String, OutputSource),
+                           selector: &str| {
+          process_name == selector || name.starts_with(selector)
+        };
+        if let Some(name) =
+          processes.iter().find(|name| !outputs.iter().any(|output| is_selected(output, name)))
+        {
+          return Ok(ClientResponse::Error {
+            message: format!("no process matching {:?} found", name),
+          });
+        }
+        if !processes.is_empty() {
+          outputs.retain(|output| processes.iter().any(|name| is_selected(output, name)));
+        }
+        // Reading through log files can take a while, so we do it without holding the lock.
+        let (matches, truncated) =
+          tokio::task::spawn_blocking(move || search_outputs(&outputs, &pattern, since_secs))
+            .await?;
+        ClientResponse::LogMatches { matches, truncated }
+      }
     })
   }
 }
@@ -1060,6 +4043,12 @@ pub async fn server_main(mut config: LaunchoConfig) -> Result<(), Error> {
   target.apply_secrets(&secrets)?;
   let global_state: &'static _ =
     Box::leak(Box::new(GlobalState::new(config.clone(), target_text, target, secrets)));
+  global_state.recover_orphans(config.server.orphans).await?;
+  if !cgroup::is_available() {
+    log_event(LogEvent::Warning {
+      msg: "cgroup v2 isn't available, so processes can't be given limits".to_string(),
+    });
+  }
 
   tokio::spawn(async move {
     loop {
@@ -1071,6 +4060,22 @@ pub async fn server_main(mut config: LaunchoConfig) -> Result<(), Error> {
       tokio::time::sleep(HOUSEKEEPING_INTERVAL).await;
     }
   });
+  tokio::spawn(async move {
+    loop {
+      if let Err(e) = global_state.prune_logs().await {
+        log_event(LogEvent::Warning {
+          msg: format!("Failed to prune old logs: {:#}", e),
+        });
+      }
+      tokio::time::sleep(LOG_PRUNE_INTERVAL).await;
+    }
+  });
+  tokio::spawn(async move {
+    loop {
+      global_state.start_due_health_checks().await;
+      tokio::time::sleep(PROBE_INTERVAL).await;
+    }
+  });
 
   let warp_global_state = warp::any().map(move || global_state);
 
@@ -1088,8 +4093,8 @@ pub async fn server_main(mut config: LaunchoConfig) -> Result<(), Error> {
     let decoded = String::from_utf8(decoded).map_err(|_| "Invalid UTF-8 inside base64")?;
     let mut split = decoded.splitn(2, ':');
     // Ignore the username.
-    let _ = split.next().ok_or_else(|| "No username")?;
-    let token = split.next().ok_or_else(|| "No token")?;
+    let _ = split.next().ok_or("No username")?;
+    let token = split.next().ok_or("No token")?;
     match constant_time_eq(token.as_bytes(), auth_config.token.as_bytes()) {
       true => Ok(()),
       false => Err("Wrong token"),
@@ -1126,7 +4131,7 @@ Authorization: Basic <base64 of "any username:server token">
 
   let check_auth = warp::header::optional::<String>("authorization")
     .and_then(validate_auth)
-    .and(warp_global_state.clone());
+    .and(warp_global_state);
 
   let api_endpoint = check_auth.and(warp::path!("api")).and(warp::body::json()).then(
     |(), global_state: &'static GlobalState, request: ClientRequest| async move {
@@ -1156,7 +4161,7 @@ Authorization: Basic <base64 of "any username:server token">
     .then(|(), _: &'static GlobalState, query: HashMap<String, String>| async move {
       fn handle_download(query: HashMap<String, String>) -> Result<Vec<u8>, Error> {
         let id = query.get("id").ok_or_else(|| anyhow!("Missing id query parameter"))?;
-        Ok(storage::read_resource(id)?)
+        storage::read_resource(id)
       }
       let builder = warp::http::Response::builder();
       match handle_download(query) {
@@ -1170,9 +4175,39 @@ Authorization: Basic <base64 of "any username:server token">
       .unwrap()
     });
 
+  let logs_endpoint = check_auth
+    .and(warp::path!("logs"))
+    .and(warp::query::<HashMap<String, String>>())
+    .and(warp::query::<LogOptions>())
+    .then(
+      |(), global_state: &'static GlobalState, query: HashMap<String, String>, options| async move {
+        let receiver = match query.get("name") {
+          Some(name) => global_state.follow_logs(name, options).await,
+          None => Err("Missing name query parameter".to_string()),
+        };
+        let builder = warp::http::Response::builder();
+        match receiver {
+          Ok(receiver) => {
+            let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+              let chunk = receiver.recv().await?;
+              Some((Ok::<_, std::convert::Infallible>(chunk), receiver))
+            });
+            builder
+              .header("Content-Type", "text/plain; charset=utf-8")
+              .body(warp::hyper::Body::wrap_stream(stream))
+          }
+          Err(message) => builder
+            .status(warp::http::StatusCode::BAD_REQUEST)
+            .body(warp::hyper::Body::from(message)),
+        }
+        .unwrap()
+      },
+    );
+
   let all_endpoints = api_endpoint
     .or(upload_endpoint)
     .or(download_endpoint)
+    .or(logs_endpoint)
     // Map rejections to a response.
     .recover(|e: warp::Rejection| async move {
       if let Some(MessageAndStatus(msg, status)) = e.find() {
@@ -1201,12 +4236,11 @@ Authorization: Basic <base64 of "any username:server token">
   use std::str::FromStr;
   let host = std::net::IpAddr::from_str(&config.server.admin_host)?;
   println!("Starting server at {}:{}", host, config.server.admin_port);
-  Ok(
-    warp::serve(all_endpoints)
-      .tls()
-      .cert(&auth_config.cert)
-      .key(auth_config.private.as_ref().unwrap())
-      .run((host, config.server.admin_port))
-      .await,
-  )
+  warp::serve(all_endpoints)
+    .tls()
+    .cert(&auth_config.cert)
+    .key(auth_config.private.as_ref().unwrap())
+    .run((host, config.server.admin_port))
+    .await;
+  Ok(())
 }
@@ -30,7 +30,7 @@ pub fn write_resource(name: String, data: &[u8]) -> Result<String, Error> {
 pub fn read_resource(id: &str) -> Result<Vec<u8>, Error> {
   let storage_dir = get_storage_dir()?;
   let resource_path = storage_dir.join(format!("{}-data", id));
-  Ok(std::fs::read(resource_path).with_context(|| format!("Failed to read resource {}", id))?)
+  std::fs::read(resource_path).with_context(|| format!("Failed to read resource {}", id))
 }
 
 pub fn copy_resource(id: &str, destination: &Path) -> Result<(), Error> {